@@ -0,0 +1,385 @@
+use bevy::{
+    animation::{AnimationClip, Keyframes, VariableCurve},
+    prelude::*,
+    transform::TransformSystem,
+};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::rig::{find_owners, RigPatterns, RigSetupError};
+
+pub struct AnimationLayerPlugin;
+
+impl Plugin for AnimationLayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(bind_animation_layers_system)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                animation_layers_system.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+// an authored clip played on part of the skeleton, the legs are left to the ik
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct AnimationLayerConfig {
+    pub clip: String,
+    pub weight: f32, // 0 keeps the rest pose, 1 plays the clip fully
+    pub speed: f32,
+    pub mask: BoneMask,
+}
+
+// regexes over bone names, a bone is animated when any bone on its path is included
+// (or the include list is empty) and the bone itself is not excluded
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct BoneMask {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub exclude_legs: bool, // leg chains found by the creature rig patterns
+}
+
+impl Default for AnimationLayerConfig {
+    // the bundled crab idle, body only
+    fn default() -> Self {
+        Self {
+            clip: "crab/crab.gltf#Animation0".to_string(),
+            weight: 1.0,
+            speed: 1.0,
+            mask: BoneMask::default(),
+        }
+    }
+}
+
+impl Default for BoneMask {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: vec!["^Armature$".to_string()], // root motion would fight the body controller
+            exclude_legs: true,
+        }
+    }
+}
+
+pub struct AnimationLayer {
+    pub clip: Handle<AnimationClip>,
+    pub weight: f32,
+    pub speed: f32,
+    pub mask: BoneMask,
+    pub elapsed: f32,
+    // bones driven by this layer with their curves, bound once the model and clip are loaded
+    pub channels: Vec<(Entity, Vec<VariableCurve>)>,
+    pub disabled: bool, // the mask didn't compile, the layer is skipped
+}
+
+// on the creature, layers are blended in order on top of the rest pose
+#[derive(Component, Default)]
+pub struct AnimationLayers {
+    pub layers: Vec<AnimationLayer>,
+    pub root: Option<Entity>,
+    pub rest_pose: Vec<(Entity, Transform)>,
+}
+
+impl AnimationLayers {
+    pub fn from_config(configs: &[AnimationLayerConfig], assets: &AssetServer) -> Self {
+        Self {
+            layers: configs
+                .iter()
+                .map(|config| AnimationLayer {
+                    clip: assets.load(config.clip.as_str()),
+                    weight: config.weight,
+                    speed: config.speed,
+                    mask: config.mask.clone(),
+                    elapsed: 0.0,
+                    channels: Vec::new(),
+                    disabled: false,
+                })
+                .collect(),
+            ..default()
+        }
+    }
+}
+
+// the scene's AnimationPlayer marks the skeleton root, the clips are sampled here
+// instead of through the player so leg bones can be masked out
+pub fn bind_animation_layers_system(
+    added_player_query: Query<Entity, Added<AnimationPlayer>>,
+    parents: Query<&Parent>,
+    mut layers_query: Query<&mut AnimationLayers>,
+) {
+    for added_entity in added_player_query.iter() {
+        let owners = find_owners(added_entity, &parents, |entity| {
+            layers_query.contains(entity)
+        });
+        let [owner] = owners[..] else {
+            continue;
+        };
+        if let Ok(mut layers) = layers_query.get_mut(owner) {
+            layers.root = Some(added_entity);
+        }
+    }
+}
+
+pub fn animation_layers_system(
+    time: Res<Time>,
+    clips: Res<Assets<AnimationClip>>,
+    mut layers_query: Query<(Entity, &mut AnimationLayers, Option<&RigPatterns>)>,
+    children: Query<&Children>,
+    names: Query<&Name>,
+    mut transforms: Query<&mut Transform>,
+) {
+    for (owner, mut layers, patterns) in layers_query.iter_mut() {
+        let Some(root) = layers.root else {
+            continue;
+        };
+        let layers = &mut *layers;
+
+        for layer in layers.layers.iter_mut() {
+            if layer.disabled || !layer.channels.is_empty() {
+                continue;
+            }
+            let Some(clip) = clips.get(&layer.clip) else {
+                continue;
+            };
+            match bind_channels(clip, root, &layer.mask, patterns, &children, &names) {
+                Ok(channels) => layer.channels = channels,
+                Err(error) => {
+                    error!("Animation layer on {:?} skipped: {}", owner, error);
+                    layer.disabled = true;
+                    continue;
+                }
+            }
+            for (bone, _) in layer.channels.iter() {
+                if layers
+                    .rest_pose
+                    .iter()
+                    .all(|(rest_bone, _)| rest_bone != bone)
+                {
+                    if let Ok(transform) = transforms.get(*bone) {
+                        layers.rest_pose.push((*bone, *transform));
+                    }
+                }
+            }
+        }
+
+        for (bone, rest) in layers.rest_pose.iter() {
+            if let Ok(mut transform) = transforms.get_mut(*bone) {
+                *transform = *rest;
+            }
+        }
+
+        for layer in layers.layers.iter_mut().filter(|layer| !layer.disabled) {
+            let Some(clip) = clips.get(&layer.clip) else {
+                continue;
+            };
+            layer.elapsed += time.delta_seconds() * layer.speed;
+            if clip.duration() > 0.0 {
+                layer.elapsed = layer.elapsed.rem_euclid(clip.duration());
+            }
+
+            for (bone, curves) in layer.channels.iter() {
+                let Ok(mut transform) = transforms.get_mut(*bone) else {
+                    continue;
+                };
+                for curve in curves.iter() {
+                    blend_curve(&mut transform, curve, layer.elapsed, layer.weight);
+                }
+            }
+        }
+    }
+}
+
+fn bind_channels(
+    clip: &AnimationClip,
+    root: Entity,
+    mask: &BoneMask,
+    patterns: Option<&RigPatterns>,
+    children: &Query<&Children>,
+    names: &Query<&Name>,
+) -> Result<Vec<(Entity, Vec<VariableCurve>)>, RigSetupError> {
+    let compile = |pattern: &String| {
+        Regex::new(pattern).map_err(|_| RigSetupError::InvalidPattern(pattern.clone()))
+    };
+    let include: Vec<Regex> = mask.include.iter().map(compile).collect::<Result<_, _>>()?;
+    let exclude: Vec<Regex> = mask.exclude.iter().map(compile).collect::<Result<_, _>>()?;
+    // everything below a leg root belongs to the leg
+    let leg_root = if mask.exclude_legs {
+        Some(compile(&patterns.cloned().unwrap_or_default().leg_root)?)
+    } else {
+        None
+    };
+
+    let mut channels = Vec::new();
+    for (path, curves) in clip.curves().iter() {
+        let matches = |regex: &Regex| path.parts.iter().any(|part| regex.is_match(part.as_str()));
+        let excluded = path.parts.last().map_or(false, |bone| {
+            exclude.iter().any(|regex| regex.is_match(bone.as_str()))
+        });
+        if excluded
+            || leg_root.as_ref().map_or(false, matches)
+            || (!include.is_empty() && !include.iter().any(matches))
+        {
+            continue;
+        }
+
+        // the first part is the root the AnimationPlayer sits on
+        let mut current = Some(root);
+        for part in path.parts.iter().skip(1) {
+            current = current.and_then(|entity| {
+                children.get(entity).ok().and_then(|entity_children| {
+                    entity_children
+                        .iter()
+                        .find(|child| names.get(**child).map_or(false, |name| name == part))
+                        .copied()
+                })
+            });
+        }
+        if let Some(bone) = current {
+            channels.push((bone, curves.clone()));
+        }
+    }
+    Ok(channels)
+}
+
+// lerps the transform towards the curve sample, holding the first and last keyframes
+fn blend_curve(transform: &mut Transform, curve: &VariableCurve, time: f32, weight: f32) {
+    let timestamps = &curve.keyframe_timestamps;
+    if timestamps.is_empty() {
+        return;
+    }
+    let next = timestamps.partition_point(|timestamp| *timestamp <= time);
+    let (start, end, t) = if next == 0 {
+        (0, 0, 0.0)
+    } else if next == timestamps.len() {
+        (next - 1, next - 1, 0.0)
+    } else {
+        let (from, to) = (timestamps[next - 1], timestamps[next]);
+        (next - 1, next, (time - from) / (to - from))
+    };
+
+    match &curve.keyframes {
+        Keyframes::Rotation(keyframes) => {
+            let from = keyframes[start].normalize();
+            let mut to = keyframes[end].normalize();
+            // shortest path
+            if to.dot(from) < 0.0 {
+                to = -to;
+            }
+            transform.rotation = transform.rotation.slerp(from.slerp(to, t), weight);
+        }
+        Keyframes::Translation(keyframes) => {
+            let sample = keyframes[start].lerp(keyframes[end], t);
+            transform.translation = transform.translation.lerp(sample, weight);
+        }
+        Keyframes::Scale(keyframes) => {
+            let sample = keyframes[start].lerp(keyframes[end], t);
+            transform.scale = transform.scale.lerp(sample, weight);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{
+        asset::LoadState,
+        ecs::system::SystemState,
+        gltf::{Gltf, GltfPlugin},
+        render::mesh::skinning::SkinnedMeshInverseBindposes,
+    };
+
+    use super::*;
+
+    // the bundled crab without a renderer, the hierarchy of its scene and its first clip
+    fn load_crab() -> (World, AnimationClip) {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<Mesh>()
+            .add_asset::<Image>()
+            .add_asset::<StandardMaterial>()
+            .add_asset::<SkinnedMeshInverseBindposes>()
+            .add_asset::<Scene>()
+            .add_asset::<AnimationClip>()
+            .add_plugin(GltfPlugin);
+
+        let gltf: Handle<Gltf> = app.world.resource::<AssetServer>().load("crab/crab.gltf");
+        for _ in 0..5000 {
+            app.update();
+            match app.world.resource::<AssetServer>().get_load_state(&gltf) {
+                LoadState::Loaded => break,
+                LoadState::Failed => panic!("crab/crab.gltf failed to load"),
+                _ => std::thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        // the labeled assets are stored on the next update
+        app.update();
+
+        let (scene, clip) = {
+            let gltf = app.world.resource::<Assets<Gltf>>().get(&gltf).unwrap();
+            (gltf.scenes[0].clone(), gltf.animations[0].clone())
+        };
+        let clip = app
+            .world
+            .resource::<Assets<AnimationClip>>()
+            .get(&clip)
+            .unwrap()
+            .clone();
+        let scene = app
+            .world
+            .resource_mut::<Assets<Scene>>()
+            .remove(scene)
+            .unwrap();
+        (scene.world, clip)
+    }
+
+    #[test]
+    fn default_mask_binds_the_crab_spine_without_root_or_legs() {
+        let (mut world, clip) = load_crab();
+        let mut state: SystemState<(
+            Query<Entity, With<AnimationPlayer>>,
+            Query<&Children>,
+            Query<&Name>,
+        )> = SystemState::new(&mut world);
+        let (players, children, names) = state.get(&world);
+
+        let channels = bind_channels(
+            &clip,
+            players.single(),
+            &BoneMask::default(),
+            None,
+            &children,
+            &names,
+        )
+        .unwrap();
+        let bound: Vec<&str> = channels
+            .iter()
+            .filter_map(|(bone, _)| names.get(*bone).ok())
+            .map(|name| name.as_str())
+            .collect();
+        assert_eq!(bound, vec!["Spine"]);
+    }
+
+    #[test]
+    fn invalid_mask_pattern_is_reported() {
+        let (mut world, clip) = load_crab();
+        let mut state: SystemState<(
+            Query<Entity, With<AnimationPlayer>>,
+            Query<&Children>,
+            Query<&Name>,
+        )> = SystemState::new(&mut world);
+        let (players, children, names) = state.get(&world);
+
+        let mask = BoneMask {
+            exclude: vec!["Leg(".to_string()],
+            ..default()
+        };
+        let result = bind_channels(&clip, players.single(), &mask, None, &children, &names);
+        assert_eq!(
+            result.unwrap_err(),
+            RigSetupError::InvalidPattern("Leg(".to_string())
+        );
+    }
+}
@@ -0,0 +1,446 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use bevy_flycam::FlyCam;
+use bevy_infinite_grid::{InfiniteGrid, InfiniteGridBundle};
+use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    animation_layers::{AnimationLayerConfig, AnimationLayers},
+    components::{
+        CreatureModel, GaitKey, GaitProfile, Ground, IdleBehaviour, IdleParams, JumpState,
+        LegRagdoll, LookAt, LookTarget, Player, SelfRighting, Stability, SurfaceMaterial,
+        WallClimbing,
+    },
+    foot_colliders::BODY_GROUP,
+    obstacles::{spawn_obstacles, ObstacleScene},
+    rig::RigPatterns,
+    terrain::{spawn_terrain, TerrainSettings},
+};
+
+// set to an .arena.ron path inside assets to load another arena
+pub const ARENA_ENV: &str = "CRAB_ARENA";
+pub const DEFAULT_ARENA: &str = "scenes/default.arena.ron";
+
+pub struct ArenaPlugin;
+
+impl Plugin for ArenaPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<ArenaConfig>()
+            .init_asset_loader::<ArenaLoader>()
+            .add_startup_system(load_arena_system)
+            .add_system(arena_spawn_system);
+    }
+}
+
+// everything a test arena needs, edited files are picked up while the app runs
+#[derive(Deserialize, TypeUuid, Debug)]
+#[uuid = "6f0e7c3a-2b8d-4d51-9a3e-8c1f4b7d2e90"]
+#[serde(default)]
+pub struct ArenaConfig {
+    pub environment: EnvironmentConfig,
+    pub camera: Option<CameraConfig>,
+    pub terrain: Option<TerrainSettings>,
+    pub obstacles: ObstacleScene,
+    pub creatures: Vec<CreatureConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct EnvironmentConfig {
+    pub light_position: Vec3,
+    pub light_illuminance: f32,
+    pub shadow_size: f32,
+    pub ground_size: f32,
+    pub ground_color: Color,
+    pub ground_surface: SurfaceMaterial,
+    pub grid: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CameraConfig {
+    pub position: Vec3,
+    pub look_at: Vec3,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct CreatureConfig {
+    pub model: String,
+    pub position: Vec3,
+    pub model_offset: Vec3,
+    pub move_speed: f32,
+    pub jump_power: f32,
+    pub walk_height: f32,
+    pub walk_spread: Vec3,
+    pub pole_offset: Vec3,
+    pub pole_spread: Vec3,
+    pub leg_reach: f32,
+    pub ride_height: f32,
+    pub step_climb_speed: f32,
+    pub gait: Vec<GaitKey>, // empty uses the default gait profile
+    pub rig: RigPatterns,
+    // authored clips blended over the body in order, the legs stay procedural
+    pub animation_layers: Vec<AnimationLayerConfig>,
+    pub idle: IdleParams,
+    pub look_at: Option<LookTargetConfig>,
+    // let the body tip over on any axis and right itself instead of staying upright
+    pub unlock_rotation: bool,
+    // uniform size, the distances and speeds above are given for scale 1
+    pub scale: f32,
+}
+
+// what a creature turns to face when the arena is built
+#[derive(Deserialize, Debug)]
+pub enum LookTargetConfig {
+    Camera,
+    Point(Vec3),
+}
+
+impl Default for ArenaConfig {
+    fn default() -> Self {
+        Self {
+            environment: EnvironmentConfig::default(),
+            camera: None,
+            terrain: Some(TerrainSettings::default()),
+            obstacles: ObstacleScene::default(),
+            creatures: vec![CreatureConfig::default()],
+        }
+    }
+}
+
+impl ArenaConfig {
+    // settings that would panic while building the arena, the load fails with the reason
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(terrain) = &self.terrain {
+            terrain.validate()?;
+        }
+        for (index, creature) in self.creatures.iter().enumerate() {
+            creature
+                .idle
+                .validate()
+                .map_err(|error| format!("creature {}: {}", index, error))?;
+        }
+        self.obstacles.validate()
+    }
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        Self {
+            light_position: Vec3::new(12.0, 18.0, 12.0),
+            light_illuminance: 10000.0,
+            shadow_size: 30.0,
+            ground_size: 200.1,
+            ground_color: Color::GRAY,
+            ground_surface: SurfaceMaterial::Sand,
+            grid: true,
+        }
+    }
+}
+
+impl Default for CreatureConfig {
+    fn default() -> Self {
+        Self {
+            model: "crab/crab.gltf#Scene0".to_string(),
+            position: Vec3::new(0.0, 12.0, 0.0),
+            model_offset: Vec3::new(0.0, -1.27, 0.0),
+            move_speed: 1.2, // m/s
+            jump_power: 2.0,
+            walk_height: 0.5,
+            walk_spread: Vec3::ONE,  // stretches the rest pose foot offsets
+            pole_offset: Vec3::ZERO, // tweaks on top of the rest pose poles
+            pole_spread: Vec3::ONE,
+            leg_reach: 2.0,
+            ride_height: 1.15, // collider half height plus border and controller offset
+            step_climb_speed: 1.5,
+            gait: Vec::new(),
+            rig: RigPatterns::default(),
+            animation_layers: vec![AnimationLayerConfig::default()],
+            idle: IdleParams::default(),
+            look_at: None,
+            unlock_rotation: false,
+            scale: 1.0,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct ArenaHandle(pub Handle<ArenaConfig>);
+
+// despawned and rebuilt when the arena file changes
+#[derive(Component)]
+pub struct ArenaEntity;
+
+#[derive(Default)]
+pub struct ArenaLoader;
+
+impl AssetLoader for ArenaLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let config: ArenaConfig = ron::de::from_bytes(bytes)?;
+            config.validate().map_err(bevy::asset::Error::msg)?;
+            load_context.set_default_asset(LoadedAsset::new(config));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["arena.ron"]
+    }
+}
+
+pub fn load_arena_system(mut commands: Commands, assets: Res<AssetServer>) {
+    let path = std::env::var(ARENA_ENV).unwrap_or_else(|_| DEFAULT_ARENA.to_string());
+    info!("Loading arena {}", path);
+    commands.insert_resource(ArenaHandle(assets.load(path)));
+}
+
+pub fn arena_spawn_system(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    assets: Res<AssetServer>,
+    arenas: Res<Assets<ArenaConfig>>,
+    arena: Res<ArenaHandle>,
+    mut events: EventReader<AssetEvent<ArenaConfig>>,
+    arena_entities: Query<Entity, With<ArenaEntity>>,
+    mut camera_query: Query<(Entity, &mut Transform), With<FlyCam>>,
+) {
+    for event in events.iter() {
+        let (handle, reload) = match event {
+            AssetEvent::Created { handle } => (handle, false),
+            AssetEvent::Modified { handle } => (handle, true),
+            AssetEvent::Removed { .. } => continue,
+        };
+        if *handle != arena.0 {
+            continue;
+        }
+        let Some(config) = arenas.get(handle) else {
+            continue;
+        };
+
+        if reload {
+            info!("Arena changed, rebuilding");
+            for entity in arena_entities.iter() {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+
+        let mut entities = spawn_environment(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            &config.environment,
+        );
+        if let Some(terrain) = &config.terrain {
+            // dunes and rocks to test foot placement on, away from the flat area
+            entities.push(spawn_terrain(
+                &mut commands,
+                &mut meshes,
+                &mut materials,
+                terrain,
+            ));
+        }
+        entities.extend(spawn_obstacles(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            &config.obstacles,
+        ));
+        // leg helpers of replaced creatures are cleaned up by despawn_leg_helpers_system
+        let camera = camera_query.iter().next().map(|(entity, _)| entity);
+        for creature in config.creatures.iter() {
+            entities.push(spawn_creature(&mut commands, &assets, creature, camera));
+        }
+        for entity in entities {
+            commands.entity(entity).insert(ArenaEntity);
+        }
+
+        if let Some(camera) = &config.camera {
+            for (_, mut transform) in camera_query.iter_mut() {
+                *transform = Transform::from_translation(camera.position)
+                    .looking_at(camera.look_at, Vec3::Y);
+            }
+        }
+    }
+}
+
+fn spawn_environment(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    environment: &EnvironmentConfig,
+) -> Vec<Entity> {
+    let mut entities = Vec::new();
+
+    let size = environment.shadow_size;
+    let light = commands.spawn(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            color: Color::WHITE,
+            illuminance: environment.light_illuminance,
+            shadows_enabled: true,
+            shadow_projection: OrthographicProjection {
+                left: -size,
+                right: size,
+                bottom: -size,
+                top: size,
+                near: -size,
+                far: size,
+                ..default()
+            },
+            ..default()
+        },
+        transform: Transform::from_translation(environment.light_position)
+            .looking_at(Vec3::ZERO, Vec3::Y),
+        ..default()
+    });
+    entities.push(light.id());
+
+    // ground
+    let ground_size = environment.ground_size;
+    let ground_height = 0.01;
+
+    let ground = commands
+        .spawn((
+            PbrBundle {
+                mesh: meshes.add(Mesh::from(shape::Plane { size: ground_size })),
+                material: materials.add(StandardMaterial {
+                    base_color: environment.ground_color,
+                    ..default()
+                }),
+                ..default()
+            },
+            Collider::cuboid(ground_size, ground_height, ground_size),
+            Ground {},
+            environment.ground_surface,
+        ))
+        .insert(Visibility { is_visible: true });
+    entities.push(ground.id());
+
+    if environment.grid {
+        let grid = commands.spawn(InfiniteGridBundle {
+            grid: InfiniteGrid {
+                // shadow_color: None,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        entities.push(grid.id());
+    }
+
+    entities
+}
+
+pub fn spawn_creature(
+    commands: &mut Commands,
+    assets: &Res<AssetServer>,
+    creature: &CreatureConfig,
+    camera: Option<Entity>,
+) -> Entity {
+    let scale = creature.scale;
+    let crab_model_translation = creature.model_offset * scale; //todo: set this dynamic so when collider step up, model step up too but lerping
+    let crab_model = commands
+        .spawn(SceneBundle {
+            scene: assets.load(creature.model.as_str()),
+            transform: Transform::from_matrix(Mat4::from_scale_rotation_translation(
+                Vec3::splat(scale),
+                Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 180.0_f32.to_radians()), //model looks to positive Z by default
+                crab_model_translation,
+            )),
+            visibility: Visibility { is_visible: true },
+            ..default()
+        })
+        .insert(CreatureModel {
+            base_translation: crab_model_translation,
+            crouch: 0.0,
+            sway: Vec3::ZERO,
+        })
+        .id();
+
+    let gait = GaitProfile::new(creature.gait.clone()).scaled(scale);
+
+    commands
+        .spawn((
+            TransformBundle::from(Transform::from_translation(creature.position)),
+            VisibilityBundle::default(),
+            Player {
+                current_speed: Vec3::ZERO,
+                move_speed: creature.move_speed * scale,
+                rotate_speed: 0.00,
+                grounded: false,
+                jumping: false,
+                jump_power: creature.jump_power * scale,
+                jump_time: 0.0,
+                jump_time_max: 0.3,
+                walk_height: creature.walk_height * scale,
+                walk_spread: creature.walk_spread,
+                pole_offset: creature.pole_offset * scale,
+                pole_spread: creature.pole_spread,
+                platform_velocity: Vec3::ZERO,
+                leg_reach: creature.leg_reach * scale,
+                ride_height: creature.ride_height * scale,
+                step_climb_speed: creature.step_climb_speed * scale,
+                up: Vec3::Y,
+                heading: 0.0,
+                scale,
+            },
+            RigidBody::Dynamic,
+            LockedAxes::ROTATION_LOCKED,
+            Collider::round_cylinder(1.0 * scale, 0.23 * scale, 0.13 * scale),
+            CollisionGroups::new(BODY_GROUP, Group::ALL),
+            GravityScale(1.0),
+            Velocity::default(),
+            ExternalForce::default(),
+            Ccd::enabled(),
+            KinematicCharacterController {
+                //translation: Some(Vec3::new(0.0, -2.1, 0.0)),
+                offset: CharacterLength::Absolute(0.01),
+                // steps are handled by the legs in ledge_step_system
+                // max_slope_climb_angle: 70.0_f32.to_radians(), //slope would make autostep not trigger if the climb angle is too high
+                // min_slope_slide_angle: 30.0_f32.to_radians(),
+                // autostep: Some(CharacterAutostep {
+                //     max_height: CharacterLength::Relative(1.25),
+                //     min_width: CharacterLength::Relative(0.5),
+                //     include_dynamic_bodies: true,
+                // }),
+                ..default()
+            },
+        ))
+        .insert((
+            LegRagdoll::default().scaled(scale),
+            SelfRighting {
+                unlock_rotation: creature.unlock_rotation,
+                ..default()
+            }
+            .scaled(scale),
+            JumpState::default().scaled(scale),
+            gait,
+            WallClimbing::default().scaled(scale),
+            Stability::default().scaled(scale),
+            creature.rig.clone(),
+            AnimationLayers::from_config(&creature.animation_layers, assets),
+            IdleBehaviour::new(creature.idle).scaled(scale),
+            LookAt {
+                target: match creature.look_at {
+                    Some(LookTargetConfig::Camera) => camera.map(LookTarget::Entity),
+                    Some(LookTargetConfig::Point(point)) => Some(LookTarget::Point(point)),
+                    None => None,
+                },
+                ..default()
+            },
+        ))
+        // .insert(Visibility {
+        //     is_visible: false,
+        // })
+        .add_child(crab_model)
+        .id()
+}
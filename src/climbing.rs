@@ -0,0 +1,97 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    components::{Player, SelfRighting, WallClimbing},
+    foot_colliders::foot_query_groups,
+    systems::{handle_move, LocomotionStage, LOCOMOTION_TIMESTEP},
+};
+
+pub struct WallClimbingPlugin;
+
+impl Plugin for WallClimbingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_stage(LocomotionStage, surface_up_system.before(handle_move))
+            .add_system_to_stage(LocomotionStage, wall_stick_system.after(handle_move));
+    }
+}
+
+// up follows a wall ahead, else the surface below, else world up so the crab falls normally
+pub fn surface_up_system(
+    rapier_context: Res<RapierContext>,
+    mut query: Query<(
+        Entity,
+        &mut Player,
+        &mut Transform,
+        &WallClimbing,
+        Option<&SelfRighting>,
+    )>,
+) {
+    let delta = LOCOMOTION_TIMESTEP as f32;
+
+    for (entity, mut player, mut transform, climbing, self_righting) in query.iter_mut() {
+        let up = player.up;
+        let origin = transform.translation;
+        let filter = QueryFilter::default()
+            .exclude_collider(entity)
+            .groups(foot_query_groups());
+
+        let wall = player
+            .planar(player.current_speed)
+            .try_normalize()
+            .and_then(|forward| {
+                rapier_context.cast_ray_and_get_normal(
+                    origin,
+                    forward,
+                    climbing.probe_distance,
+                    true,
+                    filter,
+                )
+            })
+            .map(|(_, hit)| hit.normal)
+            .filter(|normal| normal.angle_between(up) > climbing.min_wall_angle);
+
+        let below = rapier_context
+            .cast_ray_and_get_normal(origin, -up, climbing.probe_distance * 2.0, true, filter)
+            .map(|(_, hit)| hit.normal);
+
+        let target_up = wall.or(below).unwrap_or(Vec3::Y);
+        let blend = (climbing.align_speed * delta).min(1.0);
+        let step = Quat::IDENTITY.slerp(Quat::from_rotation_arc(up, target_up), blend);
+        player.up = (step * up).normalize();
+
+        // with unlocked rotation physics owns the body orientation
+        if self_righting.map_or(false, |self_righting| self_righting.unlock_rotation) {
+            continue;
+        }
+
+        // body follows the surface, the model under it goes with it
+        let body_up = transform.rotation * Vec3::Y;
+        transform.rotation = Quat::from_rotation_arc(body_up, player.up) * transform.rotation;
+    }
+}
+
+// off the floor the crab sticks to the surface instead of falling
+pub fn wall_stick_system(
+    mut query: Query<(
+        &Player,
+        &WallClimbing,
+        &mut GravityScale,
+        &mut KinematicCharacterController,
+    )>,
+) {
+    let delta = LOCOMOTION_TIMESTEP as f32;
+
+    for (player, climbing, mut gravity_scale, mut controller) in query.iter_mut() {
+        let climbing_wall = player.up.dot(Vec3::Y) < climbing.floor_threshold;
+        let scale = if climbing_wall { 0.0 } else { 1.0 };
+        if gravity_scale.0 != scale {
+            gravity_scale.0 = scale;
+        }
+
+        if climbing_wall {
+            let stick = -player.up * climbing.stick_speed * delta;
+            controller.translation = Some(controller.translation.unwrap_or(Vec3::ZERO) + stick);
+        }
+    }
+}
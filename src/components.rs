@@ -1,10 +1,12 @@
 use std::time::Duration;
 
 use bevy::{
-    prelude::{Component, Entity, ReflectComponent, Vec3},
+    prelude::{Component, Entity, Quat, ReflectComponent, Transform, Vec3},
     reflect::Reflect,
     time::Timer,
 };
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
 /**
  * Using some fields with gui inspector for debugging purposes
@@ -15,7 +17,7 @@ use bevy::{
 pub struct Player {
     pub current_speed: Vec3,
     pub move_speed: f32,
-    pub rotate_speed: f32,
+    pub rotate_speed: f32, // per second, was a per frame lerp factor before the fixed timestep
     pub grounded: bool,
     pub jumping: bool,
     pub jump_power: f32,
@@ -23,14 +25,79 @@ pub struct Player {
     pub jump_time_max: f32,
     //move
     pub walk_height: f32,
-    pub walk_width: f32,
     pub walk_spread: Vec3,
     pub pole_offset: Vec3,
     pub pole_spread: Vec3,
+    // velocity of the bodies the planted feet stand on
+    pub platform_velocity: Vec3,
+    // how far below the body the feet can still reach the ground
+    pub leg_reach: f32,
+    // distance from the body to the ground when standing
+    pub ride_height: f32,
+    pub step_climb_speed: f32,
+    // normal of the surface the crab walks on, world up unless climbing
+    pub up: Vec3,
+    // radians the body is turned about up, feet and poles are laid out around it
+    pub heading: f32,
+    // uniform size, 1 is the bundled crab. distances above are already scaled,
+    // systems use it for their own constants
+    pub scale: f32,
+}
+
+impl Player {
+    // rotates world up to the surface up, used to lay out feet, poles and movement
+    pub fn surface_rotation(&self) -> Quat {
+        Quat::from_rotation_arc(Vec3::Y, self.up)
+    }
+
+    pub fn body_rotation(&self) -> Quat {
+        self.surface_rotation() * Quat::from_rotation_y(self.heading)
+    }
+
+    pub fn planar(&self, v: Vec3) -> Vec3 {
+        v - self.up * v.dot(self.up)
+    }
+
+    pub fn planar_speed(&self) -> f32 {
+        self.planar(self.current_speed).length()
+    }
+
+    // what the legs can still reach above the ground the body stands on
+    pub fn max_step_height(&self) -> f32 {
+        (self.leg_reach - self.ride_height).max(0.0)
+    }
 }
 #[derive(Component)]
 pub struct Ground {}
 
+// surface classification for colliders, resolved by the foot raycast
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum SurfaceMaterial {
+    #[default]
+    Sand,
+    Rock,
+    Wood,
+    Metal,
+}
+
+impl SurfaceMaterial {
+    pub const ALL: [SurfaceMaterial; 4] = [
+        SurfaceMaterial::Sand,
+        SurfaceMaterial::Rock,
+        SurfaceMaterial::Wood,
+        SurfaceMaterial::Metal,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SurfaceMaterial::Sand => "sand",
+            SurfaceMaterial::Rock => "rock",
+            SurfaceMaterial::Wood => "wood",
+            SurfaceMaterial::Metal => "metal",
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct Foot {}
 
@@ -40,6 +107,10 @@ pub struct FootTarget {
     pub foot: Entity,
     pub anchor: Entity,
     pub pos_offset: Vec3,
+    pub ground: Option<Entity>,
+    pub ground_normal: Vec3,
+    pub surface: SurfaceMaterial,
+    pub grounded: bool, // false when there is no ground within leg reach
 }
 
 #[derive(Component)]
@@ -48,17 +119,574 @@ pub struct FootPole {
     pub pos_offset: Vec3,
 }
 
+// legs are counted per side from the front, index 1 is the front leg on that side
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LegSide {
+    Left,
+    Right,
+}
+
 #[derive(Component)]
 pub struct FootAnchor {
     pub owner: Entity,
+    pub side: LegSide,
+    pub index: usize, // 1 is the front leg
     pub foot: Option<Entity>,
+    pub shoulder: Option<Entity>, // first bone of the leg
+    pub leg_length: f32,          // rest pose length from the shoulder to the toe
     pub target: Option<Entity>,
     pub animation_duration: Duration,
     pub animation_timer: Timer,
     pub pos_error_margin: f32,
-    pub max_distance: f32,
+    pub max_distance: f32, // drift along the surface before stepping, at the slowest stride
     pub moving: bool,
     pub inverted: bool,
+    // ground the anchor is travelling to / planted on, copied from the target on move
+    pub ground: Option<Entity>,
+    pub ground_normal: Vec3,
+    pub surface: SurfaceMaterial,
+    // anchor transform relative to a non fixed ground body, set while planted on it
+    pub platform_local: Option<Transform>,
+}
+
+// on an anchor while its leg is taken out of the gait to reach a point
+#[derive(Component)]
+pub struct LegReach {
+    pub point: Vec3,
+    pub timer: Timer,
+}
+
+// takes a leg out of the gait to reach a world point, it steps back once the duration is over.
+// `leg` is the side and index of the anchor, `(side, 1)` for a front leg
+pub struct ReachRequest {
+    pub creature: Entity,
+    pub leg: (LegSide, usize),
+    pub point: Vec3,
+    pub duration: Duration,
+}
+
+// kinematic collider following a foot bone, spawned only when foot colliders are enabled
+#[derive(Component)]
+pub struct FootCollider {
+    pub owner: Entity,
+    pub bone: Entity,
+    pub last_position: Option<Vec3>,
+    pub velocity: Vec3,
+}
+
+// lets the surface up follow walls and ceilings
+#[derive(Component)]
+pub struct WallClimbing {
+    pub probe_distance: f32,
+    pub min_wall_angle: f32, // radians between up and a surface ahead to start climbing it
+    pub align_speed: f32,
+    pub stick_speed: f32,     // m/s pushed into the surface while off the floor
+    pub floor_threshold: f32, // up.dot(world up) above this is treated as floor, with gravity
+}
+
+impl WallClimbing {
+    pub fn scaled(self, scale: f32) -> Self {
+        Self {
+            probe_distance: self.probe_distance * scale,
+            stick_speed: self.stick_speed * scale,
+            ..self
+        }
+    }
+}
+
+impl Default for WallClimbing {
+    fn default() -> Self {
+        Self {
+            probe_distance: 1.2,
+            min_wall_angle: 45.0_f32.to_radians(),
+            align_speed: 6.0,
+            stick_speed: 1.0,
+            floor_threshold: 0.95,
+        }
+    }
+}
+
+// which legs may swing together, legs in different groups never lift at the same time
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum GaitPattern {
+    Wave,   // one leg at a time
+    Ripple, // three groups, one leg per side
+    Tripod, // front and back legs of one side with the middle leg of the other
+}
+
+impl GaitPattern {
+    // legs_per_side is the highest leg index on either side, rigs aren't all six legged
+    pub fn group(&self, side: LegSide, index: usize, legs_per_side: usize) -> usize {
+        let side = match side {
+            LegSide::Left => 0,
+            LegSide::Right => 1,
+        };
+        match self {
+            GaitPattern::Wave => side * legs_per_side + index - 1,
+            GaitPattern::Ripple => (index - 1 + side * 2) % 3,
+            GaitPattern::Tripod => (index + side) % 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct GaitParams {
+    pub stride_length: f32,
+    pub step_height: f32,
+    pub duty_factor: f32, // fraction of the cycle a foot is planted
+    pub pattern: GaitPattern,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct GaitKey {
+    pub speed: f32, // m/s
+    pub params: GaitParams,
+}
+
+// gait blend space keyed on body speed, keys sorted by speed
+#[derive(Component)]
+pub struct GaitProfile {
+    pub keys: Vec<GaitKey>,
+    pub current: GaitParams,
+}
+
+impl GaitProfile {
+    // keys may come in any order, an empty list falls back to the default profile
+    pub fn new(mut keys: Vec<GaitKey>) -> Self {
+        if keys.is_empty() {
+            return Self::default();
+        }
+        keys.sort_by(|a, b| a.speed.total_cmp(&b.speed));
+        let current = keys[0].params;
+        Self { keys, current }
+    }
+
+    // strides, step heights and the speeds they are keyed on grow with the creature
+    pub fn scaled(self, scale: f32) -> Self {
+        Self::new(
+            self.keys
+                .into_iter()
+                .map(|key| GaitKey {
+                    speed: key.speed * scale,
+                    params: GaitParams {
+                        stride_length: key.params.stride_length * scale,
+                        step_height: key.params.step_height * scale,
+                        ..key.params
+                    },
+                })
+                .collect(),
+        )
+    }
+
+    // current stride relative to the slowest key's
+    pub fn stride_scale(&self) -> f32 {
+        let base = self
+            .keys
+            .first()
+            .map_or(1.0, |key| key.params.stride_length);
+        self.current.stride_length / base.max(f32::EPSILON)
+    }
+
+    // lerps between the two closest keys, the pattern switches halfway
+    pub fn sample(&self, speed: f32) -> GaitParams {
+        let upper = self.keys.partition_point(|key| key.speed <= speed);
+        if upper == 0 {
+            return self.keys[0].params;
+        }
+        if upper == self.keys.len() {
+            return self.keys[upper - 1].params;
+        }
+
+        let (a, b) = (&self.keys[upper - 1], &self.keys[upper]);
+        let t = (speed - a.speed) / (b.speed - a.speed);
+        GaitParams {
+            stride_length: a.params.stride_length
+                + (b.params.stride_length - a.params.stride_length) * t,
+            step_height: a.params.step_height + (b.params.step_height - a.params.step_height) * t,
+            duty_factor: a.params.duty_factor + (b.params.duty_factor - a.params.duty_factor) * t,
+            pattern: if t < 0.5 {
+                a.params.pattern
+            } else {
+                b.params.pattern
+            },
+        }
+    }
+}
+
+impl Default for GaitProfile {
+    // creep, walk (1.2 m/s) and scuttle (shift, 2.04 m/s)
+    fn default() -> Self {
+        Self::new(vec![
+            GaitKey {
+                speed: 0.0,
+                params: GaitParams {
+                    stride_length: 1.0,
+                    step_height: 0.5,
+                    duty_factor: 0.85,
+                    pattern: GaitPattern::Wave,
+                },
+            },
+            GaitKey {
+                speed: 1.2,
+                params: GaitParams {
+                    stride_length: 1.0,
+                    step_height: 0.4,
+                    duty_factor: 0.7,
+                    pattern: GaitPattern::Ripple,
+                },
+            },
+            GaitKey {
+                speed: 2.0,
+                params: GaitParams {
+                    stride_length: 1.3,
+                    step_height: 0.3,
+                    duty_factor: 0.55,
+                    pattern: GaitPattern::Tripod,
+                },
+            },
+        ])
+    }
+}
+
+// leg chains switch from ik to physics bodies on knockback or long falls
+#[derive(Component)]
+pub struct LegRagdoll {
+    pub knockback_speed: f32, // velocity change in a single frame counted as a hard hit
+    pub segment_radius: f32,  // collider thickness of the leg bones
+    pub fall_time: f32,
+    pub recover_time: f32,
+    pub blend_duration: Duration,
+    pub active: bool,
+    pub airborne_time: f32,
+    pub grounded_time: f32,
+    pub landed_once: bool, // the spawn drop is not a fall
+    pub last_velocity: Vec3,
+    pub stored_constraints: Vec<StoredIkConstraint>,
+}
+
+impl LegRagdoll {
+    pub fn scaled(self, scale: f32) -> Self {
+        Self {
+            knockback_speed: self.knockback_speed * scale,
+            segment_radius: self.segment_radius * scale,
+            ..self
+        }
+    }
+}
+
+impl Default for LegRagdoll {
+    fn default() -> Self {
+        Self {
+            knockback_speed: 6.0,
+            segment_radius: 0.04,
+            fall_time: 1.0,
+            recover_time: 0.8,
+            blend_duration: Duration::from_secs_f32(0.4),
+            active: false,
+            airborne_time: 0.0,
+            grounded_time: 0.0,
+            landed_once: false,
+            last_velocity: Vec3::ZERO,
+            stored_constraints: Vec::new(),
+        }
+    }
+}
+
+// ik constraint removed from a foot while its leg is a ragdoll
+pub struct StoredIkConstraint {
+    pub foot: Entity,
+    pub chain_length: usize,
+    pub iterations: usize,
+    pub target: Entity,
+    pub pole_target: Option<Entity>,
+    pub pole_angle: f32,
+}
+
+#[derive(Component)]
+pub struct RagdollSegment {
+    pub owner: Entity,
+    pub bone: Entity,
+}
+
+// unlocks body rotation and flips the crab back when it ends up on its back or side
+#[derive(Component)]
+pub struct SelfRighting {
+    pub unlock_rotation: bool,
+    pub tip_angle: f32,     // radians from world up to start righting
+    pub recover_angle: f32, // radians from world up to stop righting
+    pub torque: f32,
+    pub angular_damping: f32,
+    pub flail_speed: f32,
+    pub flail_radius: f32,
+    pub righting: bool,
+    pub flail_time: f32,
+}
+
+impl SelfRighting {
+    // mass grows with the volume and rotational inertia with scale^5
+    pub fn scaled(self, scale: f32) -> Self {
+        Self {
+            torque: self.torque * scale.powi(5),
+            angular_damping: self.angular_damping * scale.powi(5),
+            flail_radius: self.flail_radius * scale,
+            ..self
+        }
+    }
+}
+
+impl Default for SelfRighting {
+    fn default() -> Self {
+        Self {
+            unlock_rotation: false,
+            tip_angle: 60.0_f32.to_radians(),
+            recover_angle: 15.0_f32.to_radians(),
+            torque: 400.0,
+            angular_damping: 40.0,
+            flail_speed: 14.0,
+            flail_radius: 0.25,
+            righting: false,
+            flail_time: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JumpPhase {
+    #[default]
+    Grounded,
+    Crouch,
+    TakeOff,
+    Airborne,
+    Landing,
+}
+
+// jump state machine on top of Player::jumping / Player::grounded
+#[derive(Component)]
+pub struct JumpState {
+    pub phase: JumpPhase,
+    pub phase_time: f32,
+    pub landed: bool, // true only on the locomotion tick the crab touches down
+    pub crouch_time: f32,
+    pub crouch_depth: f32,
+    pub landing_time: f32,
+    pub landing_depth: f32,
+    pub fall_grace: f32, // walking off a ledge only counts as airborne after this
+    pub tuck: f32,       // 0 keeps the stance while airborne, 1 pulls the feet under the body
+    pub tuck_height: f32,
+    pub landing_lookahead: f32, // seconds before touchdown the feet start reaching
+}
+
+impl JumpState {
+    pub fn scaled(self, scale: f32) -> Self {
+        Self {
+            crouch_depth: self.crouch_depth * scale,
+            landing_depth: self.landing_depth * scale,
+            tuck_height: self.tuck_height * scale,
+            ..self
+        }
+    }
+
+    pub fn set_phase(&mut self, phase: JumpPhase) {
+        self.phase = phase;
+        self.phase_time = 0.0;
+    }
+}
+
+impl Default for JumpState {
+    fn default() -> Self {
+        Self {
+            phase: JumpPhase::Grounded,
+            phase_time: 0.0,
+            landed: false,
+            crouch_time: 0.15,
+            crouch_depth: 0.25,
+            landing_time: 0.25,
+            landing_depth: 0.3,
+            fall_grace: 0.1,
+            tuck: 0.5,
+            tuck_height: -0.9,
+            landing_lookahead: 0.3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum LookTarget {
+    Entity(Entity),
+    Point(Vec3),
+}
+
+// twists the spine towards a target, the body turns once the twist is past the limits
+#[derive(Component)]
+pub struct LookAt {
+    pub target: Option<LookTarget>,
+    pub weight: f32,
+    pub max_yaw: f32, // radians
+    pub max_pitch: f32,
+    pub spine_speed: f32,   // how fast the spine follows, 1/s
+    pub turn_speed: f32,    // radians/s
+    pub moving_weight: f32, // weight left at full move speed, locomotion takes over
+    pub spine: Option<Entity>,
+    // current twist and what was written to the spine, to tell when an animation wrote over it
+    pub yaw: f32,
+    pub pitch: f32,
+    pub spine_base: Quat,
+    pub spine_written: Option<Quat>,
+}
+
+impl Default for LookAt {
+    fn default() -> Self {
+        Self {
+            target: None,
+            weight: 1.0,
+            max_yaw: 40.0_f32.to_radians(),
+            max_pitch: 20.0_f32.to_radians(),
+            spine_speed: 4.0,
+            turn_speed: 1.5,
+            moving_weight: 0.3,
+            spine: None,
+            yaw: 0.0,
+            pitch: 0.0,
+            spine_base: Quat::IDENTITY,
+            spine_written: None,
+        }
+    }
+}
+
+// static balance, a foot only lifts if the body stays over the feet left on the ground
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Stability {
+    pub min_margin: f32, // how far inside the support polygon the body has to stay
+    pub max_lag: f32,    // in step distances, a foot lagging further behind lifts anyway
+    // distance from the body to the closest support edge, negative outside. for debugging
+    pub margin: f32,
+}
+
+impl Stability {
+    pub fn scaled(self, scale: f32) -> Self {
+        Self {
+            min_margin: self.min_margin * scale,
+            ..self
+        }
+    }
+}
+
+impl Default for Stability {
+    fn default() -> Self {
+        Self {
+            min_margin: 0.1,
+            max_lag: 2.0,
+            margin: 0.0,
+        }
+    }
+}
+
+// the visual model under the creature body, offset to crouch or absorb landings
+#[derive(Component)]
+pub struct CreatureModel {
+    pub base_translation: Vec3,
+    pub crouch: f32,
+    pub sway: Vec3, // idle sway and weight shift, body space
+}
+
+// idle layer settings, distances are for scale 1
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct IdleParams {
+    pub seed: Option<u64>, // none picks a different one every spawn
+    pub settle_time: f32,  // seconds standing still before idling starts
+    pub blend_time: f32,
+    pub sway_amplitude: f32,
+    pub sway_period: f32,
+    pub weight_shift: f32, // how far the body leans over the feet
+    pub min_interval: f32, // seconds between idle actions
+    pub max_interval: f32,
+    pub replant_distance: f32, // feet further than this from their stance get re-planted
+    pub fidget_chance: f32,    // otherwise the weight shifts
+    pub fidget_height: f32,
+}
+
+impl IdleParams {
+    // the action interval is sampled from min to max and the sway divides by its period
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_interval > self.max_interval {
+            return Err(format!(
+                "idle min_interval {} is over max_interval {}",
+                self.min_interval, self.max_interval
+            ));
+        }
+        if self.sway_period <= 0.0 {
+            return Err(format!(
+                "idle sway_period {} must be positive",
+                self.sway_period
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for IdleParams {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            settle_time: 0.5,
+            blend_time: 1.0,
+            sway_amplitude: 0.02,
+            sway_period: 3.5,
+            weight_shift: 0.06,
+            min_interval: 1.5,
+            max_interval: 4.0,
+            replant_distance: 0.08,
+            fidget_chance: 0.4,
+            fidget_height: 0.25,
+        }
+    }
+}
+
+// subtle motion while the creature stands still, actions go through the foot anchors
+#[derive(Component)]
+pub struct IdleBehaviour {
+    pub params: IdleParams,
+    pub rng: StdRng,
+    pub idle_time: f32,
+    pub next_action: f32, // idle_time the next action runs at
+    pub blend: f32,       // 0 while moving, 1 fully idle
+    pub sway_phase: f32,
+    pub weight: Vec3,
+    pub weight_target: Vec3,
+}
+
+impl IdleBehaviour {
+    pub fn new(params: IdleParams) -> Self {
+        let rng = match params.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Self {
+            params,
+            rng,
+            idle_time: 0.0,
+            next_action: params.min_interval,
+            blend: 0.0,
+            sway_phase: 0.0,
+            weight: Vec3::ZERO,
+            weight_target: Vec3::ZERO,
+        }
+    }
+
+    pub fn scaled(self, scale: f32) -> Self {
+        Self {
+            params: IdleParams {
+                sway_amplitude: self.params.sway_amplitude * scale,
+                weight_shift: self.params.weight_shift * scale,
+                replant_distance: self.params.replant_distance * scale,
+                fidget_height: self.params.fidget_height * scale,
+                ..self.params
+            },
+            ..self
+        }
+    }
 }
 
 // impl Default for FootAnchor {
@@ -77,9 +705,145 @@ pub struct FootAnchor {
 //     }
 // }
 
+// a planted leg stepping past half a meter, for tests without a rigged model
+#[cfg(test)]
+impl FootAnchor {
+    pub fn test(owner: Entity, side: LegSide, index: usize, foot: Entity) -> Self {
+        Self {
+            owner,
+            side,
+            index,
+            foot: Some(foot),
+            shoulder: None,
+            leg_length: 1.0,
+            target: None,
+            animation_duration: Duration::from_secs_f32(0.3),
+            animation_timer: Timer::from_seconds(0.3, bevy::time::TimerMode::Once),
+            pos_error_margin: 0.2,
+            max_distance: 0.5,
+            moving: false,
+            inverted: false,
+            ground: None,
+            ground_normal: Vec3::Y,
+            surface: SurfaceMaterial::default(),
+            platform_local: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl FootTarget {
+    pub fn test(owner: Entity, foot: Entity, anchor: Entity, pos_offset: Vec3) -> Self {
+        Self {
+            owner,
+            foot,
+            anchor,
+            pos_offset,
+            ground: None,
+            ground_normal: Vec3::Y,
+            surface: SurfaceMaterial::default(),
+            grounded: true,
+        }
+    }
+}
+
+// three legs a side, a meter out from the body and 0.6 apart, with their feet
+// ride_height below it. `place` maps where each foot rests under the body to the
+// world positions of its anchor and target
+#[cfg(test)]
+pub fn spawn_test_legs(
+    world: &mut bevy::prelude::World,
+    owner: Entity,
+    ride_height: f32,
+    place: impl Fn(Vec3) -> (Vec3, Vec3),
+) {
+    use bevy::prelude::GlobalTransform;
+
+    for side in [LegSide::Left, LegSide::Right] {
+        for index in 1..=3 {
+            let x = if side == LegSide::Left { -1.0 } else { 1.0 };
+            let rest = Vec3::new(x, 0.0, (index as f32 - 2.0) * 0.6);
+            let (anchor_position, target_position) = place(rest);
+            let foot = world.spawn_empty().id();
+            let anchor = world
+                .spawn((
+                    Transform::from_translation(anchor_position),
+                    GlobalTransform::from_translation(anchor_position),
+                    FootAnchor::test(owner, side, index, foot),
+                ))
+                .id();
+            world.spawn((
+                Transform::from_translation(target_position),
+                GlobalTransform::from_translation(target_position),
+                FootTarget::test(owner, foot, anchor, rest - Vec3::Y * ride_height),
+            ));
+        }
+    }
+}
+
 //Events
 pub struct MoveAnchorEvent {
     pub anchor: Entity,
     pub target: Entity,
     pub animation_duration: Duration,
 }
+
+// sent once when an anchor finishes its step and the foot is on the ground again
+pub struct FootPlantedEvent {
+    pub owner: Entity,
+    pub anchor: Entity,
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub ground: Option<Entity>,
+    pub surface: SurfaceMaterial,
+}
+
+pub struct FootstepEvent {
+    pub owner: Entity,
+    pub surface: SurfaceMaterial,
+    pub position: Vec3,
+    pub speed: f32, // relative to move_speed, 1.0 is walking
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gait_profile_sorts_keys_and_falls_back_when_empty() {
+        let defaults = GaitProfile::default();
+        let reversed = GaitProfile::new(defaults.keys.iter().rev().copied().collect());
+        let speeds: Vec<f32> = reversed.keys.iter().map(|key| key.speed).collect();
+        assert_eq!(speeds, vec![0.0, 1.2, 2.0]);
+        assert!((reversed.sample(0.6).step_height - 0.45).abs() < 1e-6);
+
+        let empty = GaitProfile::new(Vec::new());
+        assert_eq!(empty.keys.len(), defaults.keys.len());
+    }
+
+    #[test]
+    fn idle_params_reject_empty_intervals_and_zero_periods() {
+        assert!(IdleParams::default().validate().is_ok());
+        let reversed = IdleParams {
+            min_interval: 4.0,
+            max_interval: 1.5,
+            ..IdleParams::default()
+        };
+        assert!(reversed.validate().is_err());
+        let still = IdleParams {
+            sway_period: 0.0,
+            ..IdleParams::default()
+        };
+        assert!(still.validate().is_err());
+    }
+
+    #[test]
+    fn wave_gives_every_leg_of_an_eight_legged_rig_its_own_group() {
+        let mut groups: Vec<usize> = [LegSide::Left, LegSide::Right]
+            .into_iter()
+            .flat_map(|side| (1..=4).map(move |index| GaitPattern::Wave.group(side, index, 4)))
+            .collect();
+        groups.sort();
+        assert_eq!(groups, (0..8).collect::<Vec<_>>());
+    }
+}
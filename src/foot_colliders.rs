@@ -0,0 +1,152 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::components::{FootAnchor, FootCollider, Player};
+
+// the crab body only collides with the world, feet never push their own body
+pub const BODY_GROUP: Group = Group::GROUP_1;
+pub const FOOT_GROUP: Group = Group::GROUP_2;
+
+pub struct FootColliderPlugin;
+
+impl Plugin for FootColliderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FootColliderSettings>()
+            .add_system(spawn_foot_colliders_system)
+            .add_system(foot_collider_follow_system)
+            .add_system(foot_kick_system);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FootColliderMode {
+    Disabled,
+    // only reports hits, objects are moved by the kick impulse
+    Sensor,
+    // also pushes objects through the contact solver
+    Solid,
+}
+
+#[derive(Resource)]
+pub struct FootColliderSettings {
+    pub mode: FootColliderMode,
+    pub radius: f32,
+    pub kick_strength: f32,
+    pub min_kick_speed: f32,
+}
+
+impl Default for FootColliderSettings {
+    fn default() -> Self {
+        Self {
+            mode: FootColliderMode::Disabled,
+            radius: 0.06,
+            kick_strength: 20.0,
+            min_kick_speed: 0.5,
+        }
+    }
+}
+
+// ray casts for foot placement must not hit the feet themselves
+pub fn foot_query_groups() -> CollisionGroups {
+    CollisionGroups::new(Group::ALL, Group::ALL - FOOT_GROUP)
+}
+
+// one collider for the toe (ik end effector) and one for the foot bone above it
+pub fn spawn_foot_colliders_system(
+    mut commands: Commands,
+    settings: Res<FootColliderSettings>,
+    added_anchors: Query<&FootAnchor, Added<FootAnchor>>,
+    parents: Query<&Parent>,
+    players: Query<&Player>,
+) {
+    if settings.mode == FootColliderMode::Disabled {
+        return;
+    }
+
+    for anchor in added_anchors.iter() {
+        let Some(toe) = anchor.foot else {
+            continue;
+        };
+        let foot = parents.get(toe).map(|parent| parent.get());
+        let scale = players.get(anchor.owner).map_or(1.0, |player| player.scale);
+
+        for bone in std::iter::once(toe).chain(foot.ok()) {
+            let mut collider = commands.spawn((
+                TransformBundle::default(),
+                FootCollider {
+                    owner: anchor.owner,
+                    bone,
+                    last_position: None,
+                    velocity: Vec3::ZERO,
+                },
+                RigidBody::KinematicPositionBased,
+                Collider::ball(settings.radius * scale),
+                CollisionGroups::new(FOOT_GROUP, Group::ALL - BODY_GROUP - FOOT_GROUP),
+                ActiveEvents::COLLISION_EVENTS,
+                Name::new("FootCollider"),
+            ));
+
+            if settings.mode == FootColliderMode::Sensor {
+                collider.insert(Sensor);
+            }
+        }
+    }
+}
+
+pub fn foot_collider_follow_system(
+    time: Res<Time>,
+    mut foot_colliders: Query<(&mut FootCollider, &mut Transform)>,
+    bones: Query<&GlobalTransform>,
+) {
+    let delta = time.delta_seconds();
+
+    for (mut foot_collider, mut transform) in foot_colliders.iter_mut() {
+        if let Ok(bone_transform) = bones.get(foot_collider.bone) {
+            let position = bone_transform.translation();
+            if let Some(last_position) = foot_collider.last_position {
+                if delta > 0.0 {
+                    foot_collider.velocity = (position - last_position) / delta;
+                }
+            }
+            foot_collider.last_position = Some(position);
+            transform.translation = position;
+        }
+    }
+}
+
+pub fn foot_kick_system(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    settings: Res<FootColliderSettings>,
+    foot_colliders: Query<&FootCollider>,
+    mut bodies: Query<(&RigidBody, Option<&mut ExternalImpulse>)>,
+) {
+    for event in collision_events.iter() {
+        let CollisionEvent::Started(e1, e2, _) = event else {
+            continue;
+        };
+
+        let (foot_collider, other) = match (foot_colliders.get(*e1), foot_colliders.get(*e2)) {
+            (Ok(foot_collider), _) => (foot_collider, *e2),
+            (_, Ok(foot_collider)) => (foot_collider, *e1),
+            _ => continue,
+        };
+
+        if foot_collider.velocity.length() < settings.min_kick_speed {
+            continue;
+        }
+
+        if let Ok((RigidBody::Dynamic, external_impulse)) = bodies.get_mut(other) {
+            let impulse = foot_collider.velocity * settings.kick_strength;
+            match external_impulse {
+                Some(mut external_impulse) => external_impulse.impulse += impulse,
+                None => {
+                    commands.entity(other).insert(ExternalImpulse {
+                        impulse,
+                        torque_impulse: Vec3::ZERO,
+                    });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::components::{FootPlantedEvent, Player};
+use bevy::{pbr::NotShadowCaster, prelude::*};
+use rand::Rng;
+
+pub struct FootprintPlugin;
+
+impl Plugin for FootprintPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FootprintSettings>()
+            .init_resource::<FootprintAssets>()
+            .init_resource::<FootprintPool>()
+            .add_system(spawn_footprint_system)
+            .add_system(footprint_fade_system)
+            .add_system(sand_particle_system);
+    }
+}
+
+#[derive(Resource)]
+pub struct FootprintSettings {
+    pub max_decals: usize,
+    pub lifetime: Duration,
+    pub size: f32,
+    pub color: Color,
+    pub particles_per_step: usize,
+    pub particle_lifetime: Duration,
+    pub particle_speed: f32,
+}
+
+impl Default for FootprintSettings {
+    fn default() -> Self {
+        Self {
+            max_decals: 64,
+            lifetime: Duration::from_secs_f32(6.0),
+            size: 0.12,
+            color: Color::rgba(0.25, 0.22, 0.18, 0.8),
+            particles_per_step: 6,
+            particle_lifetime: Duration::from_secs_f32(0.5),
+            particle_speed: 0.8,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct FootprintAssets {
+    decal_mesh: Handle<Mesh>,
+    particle_mesh: Handle<Mesh>,
+    particle_material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for FootprintAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let decal_mesh = meshes.add(Mesh::from(shape::Quad::new(Vec2::new(1.0, 1.4))));
+        let particle_mesh = meshes.add(Mesh::from(shape::Icosphere {
+            radius: 0.02,
+            subdivisions: 0,
+        }));
+
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let particle_material = materials.add(StandardMaterial {
+            base_color: Color::rgb(0.76, 0.7, 0.5),
+            ..default()
+        });
+
+        Self {
+            decal_mesh,
+            particle_mesh,
+            particle_material,
+        }
+    }
+}
+
+// oldest decal first, reused once max_decals is reached
+#[derive(Resource, Default)]
+pub struct FootprintPool {
+    decals: VecDeque<Entity>,
+}
+
+#[derive(Component)]
+pub struct Footprint {
+    timer: Timer,
+}
+
+#[derive(Component)]
+pub struct SandParticle {
+    velocity: Vec3,
+    timer: Timer,
+}
+
+pub fn spawn_footprint_system(
+    mut commands: Commands,
+    mut reader: EventReader<FootPlantedEvent>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut pool: ResMut<FootprintPool>,
+    settings: Res<FootprintSettings>,
+    assets: Res<FootprintAssets>,
+    decal_materials: Query<&Handle<StandardMaterial>, With<Footprint>>,
+    players: Query<&Player>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for event in reader.iter() {
+        let scale = players.get(event.owner).map_or(1.0, |player| player.scale);
+        let normal = event.normal.try_normalize().unwrap_or(Vec3::Y);
+        let transform = Transform {
+            translation: event.position + normal * 0.01, // avoid z-fighting with the ground
+            rotation: Quat::from_rotation_arc(Vec3::Z, normal),
+            scale: Vec3::splat(settings.size * scale),
+        };
+        let footprint = Footprint {
+            timer: Timer::new(settings.lifetime, TimerMode::Once),
+        };
+
+        let reused = if pool.decals.len() >= settings.max_decals {
+            pool.decals.pop_front()
+        } else {
+            None
+        };
+
+        let decal = match reused {
+            Some(decal) => {
+                if let Ok(handle) = decal_materials.get(decal) {
+                    if let Some(material) = materials.get_mut(handle) {
+                        material.base_color = settings.color;
+                    }
+                }
+                commands.entity(decal).insert((
+                    transform,
+                    footprint,
+                    Visibility { is_visible: true },
+                ));
+                decal
+            }
+            None => commands
+                .spawn((
+                    PbrBundle {
+                        mesh: assets.decal_mesh.clone(),
+                        material: materials.add(StandardMaterial {
+                            base_color: settings.color,
+                            alpha_mode: AlphaMode::Blend,
+                            perceptual_roughness: 1.0,
+                            ..default()
+                        }),
+                        transform,
+                        ..default()
+                    },
+                    footprint,
+                    NotShadowCaster,
+                    Name::new("Footprint"),
+                ))
+                .id(),
+        };
+        pool.decals.push_back(decal);
+
+        for _ in 0..settings.particles_per_step {
+            let spread = Vec3::new(rng.gen_range(-1.0..=1.0), 0.0, rng.gen_range(-1.0..=1.0));
+            let velocity =
+                (normal * rng.gen_range(0.5..=1.0) + spread * 0.5) * settings.particle_speed;
+            commands.spawn((
+                PbrBundle {
+                    mesh: assets.particle_mesh.clone(),
+                    material: assets.particle_material.clone(),
+                    transform: Transform::from_translation(event.position + normal * 0.02),
+                    ..default()
+                },
+                SandParticle {
+                    velocity,
+                    timer: Timer::new(settings.particle_lifetime, TimerMode::Once),
+                },
+                NotShadowCaster,
+            ));
+        }
+    }
+}
+
+pub fn footprint_fade_system(
+    time: Res<Time>,
+    settings: Res<FootprintSettings>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut footprints: Query<(&mut Footprint, &Handle<StandardMaterial>, &mut Visibility)>,
+) {
+    for (mut footprint, handle, mut visibility) in footprints.iter_mut() {
+        if !visibility.is_visible {
+            continue;
+        }
+
+        footprint.timer.tick(time.delta());
+        if footprint.timer.finished() {
+            visibility.is_visible = false; // stays in the pool until reused
+            continue;
+        }
+
+        if let Some(material) = materials.get_mut(handle) {
+            let alpha = settings.color.a() * (1.0 - footprint.timer.percent());
+            material.base_color.set_a(alpha);
+        }
+    }
+}
+
+pub fn sand_particle_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particles: Query<(Entity, &mut SandParticle, &mut Transform)>,
+) {
+    let gravity = Vec3::new(0.0, -9.81, 0.0);
+    let delta = time.delta_seconds();
+
+    for (entity, mut particle, mut transform) in particles.iter_mut() {
+        particle.timer.tick(time.delta());
+        if particle.timer.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        particle.velocity += gravity * delta;
+        transform.translation += particle.velocity * delta;
+        transform.scale = Vec3::splat(1.0 - particle.timer.percent());
+    }
+}
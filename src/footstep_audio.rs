@@ -0,0 +1,119 @@
+use std::path::Path;
+
+use bevy::{
+    asset::{AssetIo, LoadState},
+    prelude::*,
+    utils::HashMap,
+};
+use rand::Rng;
+
+use crate::{
+    components::{FootPlantedEvent, FootstepEvent, Player, SurfaceMaterial},
+    systems::RUN_SPEED_FACTOR,
+};
+
+pub struct FootstepAudioPlugin;
+
+impl Plugin for FootstepAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<FootstepEvent>()
+            .init_resource::<FootstepSoundBank>()
+            .add_system(footstep_event_system)
+            .add_system(footstep_audio_system);
+    }
+}
+
+#[derive(Resource)]
+pub struct FootstepSoundBank {
+    pub sounds: HashMap<SurfaceMaterial, Vec<Handle<AudioSource>>>,
+    pub min_volume: f32,
+    pub max_volume: f32,
+    pub pitch_variation: f32,
+}
+
+// loads sounds/footsteps/{surface}_{n}.ogg, n in 1..=VARIATIONS. missing files are left out
+impl FromWorld for FootstepSoundBank {
+    fn from_world(world: &mut World) -> Self {
+        const VARIATIONS: usize = 3;
+        let asset_server = world.resource::<AssetServer>();
+        let sounds = SurfaceMaterial::ALL
+            .iter()
+            .map(|surface| {
+                let variations = (1..=VARIATIONS)
+                    .map(|n| format!("sounds/footsteps/{}_{}.ogg", surface.name(), n))
+                    .filter(|path| {
+                        asset_server
+                            .asset_io()
+                            .get_metadata(Path::new(path))
+                            .map_or(false, |metadata| metadata.is_file())
+                    })
+                    .map(|path| asset_server.load(path))
+                    .collect();
+                (*surface, variations)
+            })
+            .collect();
+
+        Self {
+            sounds,
+            min_volume: 0.1,
+            max_volume: 0.8,
+            pitch_variation: 0.1,
+        }
+    }
+}
+
+pub fn footstep_event_system(
+    mut planted_reader: EventReader<FootPlantedEvent>,
+    mut footstep_writer: EventWriter<FootstepEvent>,
+    player_query: Query<&Player>,
+) {
+    for event in planted_reader.iter() {
+        let speed = match player_query.get(event.owner) {
+            Ok(player) if player.move_speed > 0.0 => {
+                player.current_speed.length() / player.move_speed
+            }
+            _ => 0.0,
+        };
+
+        footstep_writer.send(FootstepEvent {
+            owner: event.owner,
+            surface: event.surface,
+            position: event.position,
+            speed,
+        });
+    }
+}
+
+pub fn footstep_audio_system(
+    mut reader: EventReader<FootstepEvent>,
+    bank: Res<FootstepSoundBank>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for event in reader.iter() {
+        let Some(variations) = bank.sounds.get(&event.surface) else {
+            continue;
+        };
+        // a clip that failed to decode would wait in the audio queue forever
+        let variations: Vec<&Handle<AudioSource>> = variations
+            .iter()
+            .filter(|handle| asset_server.get_load_state(*handle) != LoadState::Failed)
+            .collect();
+        if variations.is_empty() {
+            continue;
+        }
+
+        let sound = variations[rng.gen_range(0..variations.len())].clone();
+        // full volume when running
+        let loudness = (event.speed / RUN_SPEED_FACTOR).clamp(0.0, 1.0);
+        let volume = bank.min_volume + (bank.max_volume - bank.min_volume) * loudness;
+        let pitch = 1.0 + rng.gen_range(-bank.pitch_variation..=bank.pitch_variation);
+
+        audio.play_with_settings(
+            sound,
+            PlaybackSettings::ONCE.with_volume(volume).with_speed(pitch),
+        );
+    }
+}
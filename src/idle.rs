@@ -0,0 +1,180 @@
+use std::{f32::consts::TAU, time::Duration};
+
+use bevy::prelude::*;
+use bevy_tweening::{lens::TransformPositionLens, Animator, EaseFunction, Tween};
+use rand::Rng;
+
+use crate::{
+    components::{
+        CreatureModel, FootAnchor, FootTarget, IdleBehaviour, JumpPhase, JumpState,
+        MoveAnchorEvent, Player, SelfRighting,
+    },
+    jump::creature_model_offset_system,
+};
+
+pub struct IdlePlugin;
+
+impl Plugin for IdlePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(idle_system).add_system(
+            idle_sway_system
+                .after(idle_system)
+                .before(creature_model_offset_system),
+        );
+    }
+}
+
+// counts how long the creature has stood still and runs an idle action every few seconds:
+// re-planting the foot furthest from its stance, shifting the weight or tapping a foot
+pub fn idle_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut player_query: Query<(
+        Entity,
+        &Player,
+        &mut IdleBehaviour,
+        Option<&JumpState>,
+        Option<&SelfRighting>,
+    )>,
+    target_query: Query<(Entity, &FootTarget, &GlobalTransform)>,
+    mut anchor_query: Query<(&mut FootAnchor, &Transform)>,
+    mut move_event_writer: EventWriter<MoveAnchorEvent>,
+) {
+    let delta = time.delta_seconds();
+
+    for (entity, player, mut idle, jump_state, self_righting) in player_query.iter_mut() {
+        let idle = &mut *idle;
+        let standing = player.grounded
+            && player.planar_speed() < f32::EPSILON
+            && jump_state.map_or(true, |jump_state| jump_state.phase == JumpPhase::Grounded)
+            && !self_righting.map_or(false, |self_righting| self_righting.righting);
+
+        if standing {
+            idle.idle_time += delta;
+        } else {
+            idle.idle_time = 0.0;
+            idle.next_action = idle.params.min_interval;
+            idle.weight_target = Vec3::ZERO;
+        }
+        let settled = idle.idle_time > idle.params.settle_time;
+        let blend_step = delta / idle.params.blend_time.max(f32::EPSILON);
+        idle.blend = if settled {
+            (idle.blend + blend_step).min(1.0)
+        } else {
+            (idle.blend - blend_step).max(0.0)
+        };
+        idle.sway_phase = (idle.sway_phase + delta * TAU / idle.params.sway_period) % TAU;
+
+        if !settled || idle.idle_time < idle.next_action {
+            continue;
+        }
+        // one foot at a time, the walking gait may still be finishing a step
+        let legs: Vec<(Entity, &FootTarget, &GlobalTransform)> = target_query
+            .iter()
+            .filter(|(_, foot_target, _)| foot_target.owner == entity)
+            .collect();
+        let any_moving = legs.iter().any(|(_, foot_target, _)| {
+            anchor_query
+                .get(foot_target.anchor)
+                .map_or(false, |(anchor, _)| anchor.moving)
+        });
+        if any_moving {
+            continue;
+        }
+        idle.next_action = idle.idle_time
+            + idle
+                .rng
+                .gen_range(idle.params.min_interval..=idle.params.max_interval);
+
+        // the foot that drifted the most from where it would stand comfortably
+        let drifted = legs
+            .iter()
+            .filter(|(_, foot_target, _)| foot_target.grounded)
+            .filter_map(|(target_entity, foot_target, target_transform)| {
+                let (_, anchor_transform) = anchor_query.get(foot_target.anchor).ok()?;
+                let drift = player
+                    .planar(anchor_transform.translation - target_transform.translation())
+                    .length();
+                Some((*target_entity, foot_target.anchor, drift))
+            })
+            .max_by(|(_, _, a), (_, _, b)| a.total_cmp(b));
+
+        match drifted {
+            Some((target_entity, anchor_entity, drift)) if drift > idle.params.replant_distance => {
+                if let Ok((mut anchor, _)) = anchor_query.get_mut(anchor_entity) {
+                    move_event_writer.send(MoveAnchorEvent {
+                        anchor: anchor_entity,
+                        target: target_entity,
+                        animation_duration: anchor.animation_duration,
+                    });
+                    anchor.moving = true;
+                }
+            }
+            _ if idle.rng.gen::<f32>() < idle.params.fidget_chance && !legs.is_empty() => {
+                let (_, foot_target, _) = legs[idle.rng.gen_range(0..legs.len())];
+                let height = idle.params.fidget_height * idle.rng.gen_range(0.5..=1.0);
+                if let Ok((mut anchor, anchor_transform)) = anchor_query.get_mut(foot_target.anchor)
+                {
+                    // lift and put back down on the same spot, planting when the timer ends
+                    let half =
+                        (anchor.animation_timer.duration() / 2).max(Duration::from_millis(1));
+                    let start = anchor_transform.translation;
+                    let top = start + player.up * height;
+                    let tap = Tween::new(
+                        EaseFunction::QuadraticOut,
+                        half,
+                        TransformPositionLens { start, end: top },
+                    )
+                    .then(Tween::new(
+                        EaseFunction::QuadraticIn,
+                        half,
+                        TransformPositionLens {
+                            start: top,
+                            end: start,
+                        },
+                    ));
+                    commands
+                        .entity(foot_target.anchor)
+                        .insert(Animator::new(tap));
+                    anchor.animation_timer.reset();
+                    anchor.moving = true;
+                }
+            }
+            _ => {
+                // lean towards a random side, or back to the middle
+                idle.weight_target = if idle.weight_target == Vec3::ZERO {
+                    let angle = idle.rng.gen_range(0.0..TAU);
+                    Vec3::new(angle.cos(), 0.0, angle.sin()) * idle.params.weight_shift
+                } else {
+                    Vec3::ZERO
+                };
+            }
+        }
+    }
+}
+
+// the model sways and leans over the planted feet, the ik keeps them in place
+pub fn idle_sway_system(
+    time: Res<Time>,
+    mut idle_query: Query<&mut IdleBehaviour>,
+    mut model_query: Query<(&Parent, &mut CreatureModel)>,
+) {
+    for (parent, mut model) in model_query.iter_mut() {
+        let Ok(mut idle) = idle_query.get_mut(parent.get()) else {
+            continue;
+        };
+        let blend = (time.delta_seconds() * 2.0).min(1.0);
+        idle.weight = idle.weight.lerp(idle.weight_target, blend);
+
+        let amplitude = idle.params.sway_amplitude;
+        let sway = Vec3::new(
+            idle.sway_phase.sin() * amplitude,
+            (idle.sway_phase * 2.0).sin() * amplitude * 0.5, // breathing, twice per sway
+            0.0,
+        );
+        let offset = (sway + idle.weight) * idle.blend;
+        if model.sway != offset {
+            model.sway = offset;
+        }
+    }
+}
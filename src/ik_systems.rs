@@ -1,6 +1,10 @@
 use std::time::Duration;
 
-use crate::components::{Foot, FootAnchor, FootPole, FootTarget, Player};
+use crate::components::{
+    Foot, FootAnchor, FootCollider, FootPole, FootTarget, LegSide, Player, RagdollSegment,
+    SurfaceMaterial,
+};
+use crate::rig::{discover_legs, find_owners, RigPatterns, RigSetupError, RigStatus};
 use bevy::prelude::*;
 use bevy_mod_inverse_kinematics::IkConstraint;
 struct KinematicLeg {
@@ -16,162 +20,91 @@ pub fn on_added_setup_ik(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     added_player_query: Query<Entity, Added<AnimationPlayer>>,
-    //children: Query<(&AnimationPlayer, &Children)>,
     children: Query<&Children>,
     names: Query<&Name>,
-    player_query: Query<(Entity, With<Player>)>,
+    parents: Query<&Parent>,
+    transforms: Query<&Transform>,
+    player_query: Query<(&Player, Option<&RigPatterns>)>,
 ) {
-    let player_entity = player_query.get_single().unwrap().0;
-    let spread = Vec3::new(1.5, -1.0, 1.33);
-    let chain_length = 2;
-    let joints_amount = 4;
-
     for added_entity in added_player_query.iter() {
-        generate_leg_kinematics(
-            player_entity,
-            find_entity(
-                &EntityPath {
-                    parts: get_parts("L", 1, joints_amount),
-                },
-                added_entity,
-                &children,
-                &names,
-            )
-            .unwrap(),
-            &mut commands,
-            &mut meshes,
-            &mut materials,
-            spread * Vec3::new(-0.6, 0.01, -0.33),
-            chain_length,
-            20,
-            0.14,
-            false,
-        );
-
-        generate_leg_kinematics(
-            player_entity,
-            find_entity(
-                &EntityPath {
-                    parts: get_parts("L", 2, joints_amount),
-                },
-                added_entity,
-                &children,
-                &names,
-            )
-            .unwrap(),
-            &mut commands,
-            &mut meshes,
-            &mut materials,
-            spread * Vec3::new(-0.8, 0.01, 0.0),
-            chain_length,
-            20,
-            0.25,
-            true,
-        );
-
-        generate_leg_kinematics(
-            player_entity,
-            find_entity(
-                &EntityPath {
-                    parts: get_parts("L", 3, joints_amount),
-                },
-                added_entity,
-                &children,
-                &names,
-            )
-            .unwrap(),
-            &mut commands,
-            &mut meshes,
-            &mut materials,
-            spread * Vec3::new(-0.5, 0.01, 0.6),
-            chain_length,
-            20,
-            0.20,
-            false,
-        );
-
-        generate_leg_kinematics(
-            player_entity,
-            find_entity(
-                &EntityPath {
-                    parts: get_parts("R", 1, joints_amount),
-                },
-                added_entity,
-                &children,
-                &names,
-            )
-            .unwrap(),
-            &mut commands,
-            &mut meshes,
-            &mut materials,
-            spread * Vec3::new(0.6, 0.01, -0.3),
-            chain_length,
-            20,
-            0.25,
-            true,
-        );
-
-        generate_leg_kinematics(
-            player_entity,
-            find_entity(
-                &EntityPath {
-                    parts: get_parts("R", 2, joints_amount),
-                },
-                added_entity,
-                &children,
-                &names,
-            )
-            .unwrap(),
-            &mut commands,
-            &mut meshes,
-            &mut materials,
-            spread * Vec3::new(0.7, 0.01, 0.2),
-            chain_length,
-            20,
-            0.20,
-            false,
-        );
-
-        generate_leg_kinematics(
+        let owners = find_owners(added_entity, &parents, |entity| {
+            player_query.contains(entity)
+        });
+        let error = RigSetupError::AmbiguousOwner {
+            scene: added_entity,
+            owners: owners.len(),
+        };
+        let [player_entity] = owners[..] else {
+            error!("Rig setup failed: {}", error);
+            for owner in owners {
+                commands.entity(owner).insert(RigStatus {
+                    legs: 0,
+                    errors: vec![error.clone()],
+                });
+            }
+            continue;
+        };
+        let Ok((player, patterns)) = player_query.get(player_entity) else {
+            continue;
+        };
+        let patterns = patterns.cloned().unwrap_or_default();
+
+        let (legs, errors) = discover_legs(
+            added_entity,
             player_entity,
-            find_entity(
-                &EntityPath {
-                    parts: get_parts("R", 3, joints_amount),
-                },
-                added_entity,
-                &children,
-                &names,
-            )
-            .unwrap(),
-            &mut commands,
-            &mut meshes,
-            &mut materials,
-            spread * Vec3::new(0.5, 0.01, 0.6),
-            chain_length,
-            20,
-            0.25,
-            true,
+            &patterns,
+            &children,
+            &names,
+            &parents,
+            &transforms,
         );
+        let status = RigStatus {
+            legs: legs.len(),
+            errors,
+        };
+        if status.is_ok() {
+            info!("Rigged {} legs on {:?}", status.legs, player_entity);
+        } else {
+            for error in status.errors.iter() {
+                error!("Rig setup on {:?}: {}", player_entity, error);
+            }
+        }
+        commands.entity(player_entity).insert(status);
+
+        for leg in legs.iter() {
+            generate_leg_kinematics(
+                player_entity,
+                leg.side,
+                leg.index,
+                leg.bones[0],
+                leg.length(),
+                leg.foot(),
+                &mut commands,
+                &mut meshes,
+                &mut materials,
+                leg.default_offset(player, &patterns),
+                leg.default_pole(player, &patterns),
+                patterns.chain_length,
+                patterns.ik_iterations,
+                leg.step_distance(&patterns),
+                leg.starts_inverted(),
+            );
+        }
     }
 }
 
-fn get_parts(prefix: &str, number: i32, size: usize) -> Vec<Name> {
-    let a = vec![
-        format!("{}.Shoulder.00{}", prefix, number).into(),
-        format!("{}.Leg.00{}", prefix, number).into(),
-        format!("{}.Foot.00{}", prefix, number).into(),
-        format!("{}.Toe.00{}", prefix, number).into(),
-    ];
-    a[0..size].to_vec()
-}
-
 fn generate_leg_kinematics(
     player_entity: Entity,
+    side: LegSide,
+    index: usize,
+    shoulder_entity: Entity,
+    leg_length: f32,
     foot_entity: Entity,
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
     offset_spread: Vec3,
+    pole_offset: Vec3,
     chain_length: usize,
     iterations: usize,
     distance: f32,
@@ -181,9 +114,7 @@ fn generate_leg_kinematics(
     let pole = commands
         .spawn((
             PbrBundle {
-                transform: Transform::from_translation(
-                    offset_spread * Vec3::new(3.5, 1.0, 3.0) + Vec3::new(0.0, 2.0, 0.0),
-                ),
+                transform: Transform::from_translation(pole_offset),
                 mesh: meshes.add(Mesh::from(shape::Icosphere {
                     radius: 0.05,
                     subdivisions: 1,
@@ -196,7 +127,7 @@ fn generate_leg_kinematics(
             },
             FootPole {
                 owner: player_entity,
-                pos_offset: offset_spread * Vec3::new(3.5, 1.0, 3.0) + Vec3::new(0.0, 2.0, 0.0), //todo: remove?
+                pos_offset: pole_offset,
             },
         ))
         .id();
@@ -217,7 +148,11 @@ fn generate_leg_kinematics(
             },
             FootAnchor {
                 owner: player_entity,
+                side,
+                index,
                 foot: Some(foot_entity),
+                shoulder: Some(shoulder_entity),
+                leg_length,
                 target: None,
                 animation_duration: Duration::from_secs_f32(distance * 1.2),
                 animation_timer: Timer::new(Duration::from_secs_f32(distance * 1.2), TimerMode::Once),
@@ -225,6 +160,10 @@ fn generate_leg_kinematics(
                 max_distance: distance,
                 moving: false,
                 inverted,
+                ground: None,
+                ground_normal: Vec3::Y,
+                surface: SurfaceMaterial::default(),
+                platform_local: None,
             },
         ))
         .id();
@@ -248,6 +187,10 @@ fn generate_leg_kinematics(
                 foot: foot_entity,
                 anchor: anchor,
                 pos_offset: offset_spread,
+                ground: None,
+                ground_normal: Vec3::Y,
+                surface: SurfaceMaterial::default(),
+                grounded: false,
             },
         ))
         .id();
@@ -267,36 +210,36 @@ fn generate_leg_kinematics(
     // .add_child(pole);
 }
 
-fn find_entity(
-    path: &EntityPath,
-    root: Entity,
-    children: &Query<&Children>,
-    names: &Query<&Name>,
-) -> Result<Entity, ()> {
-    let mut current_entity = root;
+// leg helpers are root entities, they go with the creature that owns them.
+// runs last so despawns from any earlier stage this frame are seen
+pub fn despawn_leg_helpers_system(
+    mut commands: Commands,
+    removed_players: RemovedComponents<Player>,
+    helpers: Query<(
+        Entity,
+        AnyOf<(
+            &FootAnchor,
+            &FootTarget,
+            &FootPole,
+            &FootCollider,
+            &RagdollSegment,
+        )>,
+    )>,
+) {
+    let removed: Vec<Entity> = removed_players.iter().collect();
+    if removed.is_empty() {
+        return;
+    }
 
-    for part in path.parts.iter() {
-        let mut found = false;
-        if let Ok(children) = children.get(current_entity) {
-            for child in children.iter() {
-                match part.as_str() {
-                    _ => {
-                        if let Ok(name) = names.get(*child) {
-                            if name == part {
-                                current_entity = *child;
-                                found = true;
-                                break;
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        if !found {
-            warn!("Entity not found for path {:?} on part {:?}", path, part); // not happening
-            return Err(());
+    for (entity, (anchor, target, pole, collider, segment)) in helpers.iter() {
+        let owner = anchor
+            .map(|anchor| anchor.owner)
+            .or(target.map(|target| target.owner))
+            .or(pole.map(|pole| pole.owner))
+            .or(collider.map(|collider| collider.owner))
+            .or(segment.map(|segment| segment.owner));
+        if owner.map_or(false, |owner| removed.contains(&owner)) {
+            commands.entity(entity).despawn_recursive();
         }
     }
-
-    Ok(current_entity)
 }
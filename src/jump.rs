@@ -0,0 +1,212 @@
+use std::{f32::consts::PI, time::Duration};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use bevy_tweening::Animator;
+
+use crate::{
+    components::{
+        CreatureModel, FootAnchor, FootTarget, JumpPhase, JumpState, MoveAnchorEvent, Player,
+    },
+    foot_colliders::foot_query_groups,
+    systems::{
+        anchor_move_event_trigger_system, handle_move, LocomotionStage, LOCOMOTION_TIMESTEP,
+    },
+};
+
+pub struct JumpPlugin;
+
+impl Plugin for JumpPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_stage(LocomotionStage, jump_state_system.after(handle_move))
+            .add_system_to_stage(
+                LocomotionStage,
+                landing_legs_system
+                    .after(jump_state_system)
+                    .before(anchor_move_event_trigger_system),
+            )
+            .add_system(jump_crouch_system)
+            .add_system(creature_model_offset_system.after(jump_crouch_system))
+            .add_system(airborne_legs_system);
+    }
+}
+
+pub fn jump_state_system(
+    mut query: Query<(Entity, &mut Player, &mut JumpState)>,
+    target_query: Query<&FootTarget>,
+) {
+    for (entity, mut player, mut jump_state) in query.iter_mut() {
+        jump_state.phase_time += LOCOMOTION_TIMESTEP as f32;
+        jump_state.landed = false;
+
+        // the body can rest on a ledge while none of the feet reach the ground
+        let ground_in_reach = target_query
+            .iter()
+            .any(|foot_target| foot_target.owner == entity && foot_target.grounded);
+        let supported = player.grounded && ground_in_reach;
+
+        match jump_state.phase {
+            JumpPhase::Grounded => {
+                if !supported && jump_state.phase_time > jump_state.fall_grace {
+                    jump_state.set_phase(JumpPhase::Airborne);
+                } else if supported {
+                    jump_state.phase_time = 0.0;
+                }
+            }
+            JumpPhase::Crouch => {
+                if jump_state.phase_time >= jump_state.crouch_time {
+                    player.jumping = true;
+                    player.jump_time = 0.0;
+                    jump_state.set_phase(JumpPhase::TakeOff);
+                }
+            }
+            JumpPhase::TakeOff => {
+                if !player.grounded {
+                    jump_state.set_phase(JumpPhase::Airborne);
+                } else if !player.jumping {
+                    // never left the ground, e.g. jumped into a ceiling
+                    jump_state.set_phase(JumpPhase::Landing);
+                    jump_state.landed = true;
+                }
+            }
+            JumpPhase::Airborne => {
+                if supported {
+                    jump_state.set_phase(JumpPhase::Landing);
+                    jump_state.landed = true;
+                }
+            }
+            JumpPhase::Landing => {
+                if jump_state.phase_time >= jump_state.landing_time {
+                    jump_state.set_phase(JumpPhase::Grounded);
+                }
+            }
+        }
+    }
+}
+
+// body dips before take-off and when absorbing a landing
+pub fn jump_crouch_system(
+    jump_query: Query<&JumpState>,
+    mut model_query: Query<(&Parent, &mut CreatureModel)>,
+) {
+    for (parent, mut model) in model_query.iter_mut() {
+        let Ok(jump_state) = jump_query.get(parent.get()) else {
+            continue;
+        };
+
+        let crouch = match jump_state.phase {
+            JumpPhase::Crouch => {
+                let t = (jump_state.phase_time / jump_state.crouch_time).clamp(0.0, 1.0);
+                jump_state.crouch_depth * t * t * (3.0 - 2.0 * t)
+            }
+            JumpPhase::Landing => {
+                let t = (jump_state.phase_time / jump_state.landing_time).clamp(0.0, 1.0);
+                jump_state.landing_depth * (t * PI).sin()
+            }
+            _ => 0.0,
+        };
+        // only touch the model when it moves, creature_model_offset_system runs on change
+        if model.crouch != crouch {
+            model.crouch = crouch;
+        }
+    }
+}
+
+pub fn creature_model_offset_system(
+    mut model_query: Query<(&CreatureModel, &mut Transform), Changed<CreatureModel>>,
+) {
+    for (model, mut transform) in model_query.iter_mut() {
+        transform.translation = model.base_translation - Vec3::Y * model.crouch + model.sway;
+    }
+}
+
+// all feet reach for the ground together on the tick the body lands,
+// `landed` is only set for that tick so this has to run in the same stage
+pub fn landing_legs_system(
+    player_query: Query<&JumpState>,
+    target_query: Query<(Entity, &FootTarget)>,
+    mut anchor_query: Query<&mut FootAnchor>,
+    mut move_event_writer: EventWriter<MoveAnchorEvent>,
+) {
+    for (target_entity, foot_target) in target_query.iter() {
+        let Ok(jump_state) = player_query.get(foot_target.owner) else {
+            continue;
+        };
+        if !jump_state.landed {
+            continue;
+        }
+        let Ok(mut anchor) = anchor_query.get_mut(foot_target.anchor) else {
+            continue;
+        };
+
+        move_event_writer.send(MoveAnchorEvent {
+            anchor: foot_target.anchor,
+            target: target_entity,
+            animation_duration: Duration::from_secs_f32(jump_state.landing_time * 0.5),
+        });
+        anchor.moving = true;
+    }
+}
+
+// feet dangle tucked under the body while airborne or out of reach of the ground,
+// and reach for the ground right before landing
+pub fn airborne_legs_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    player_query: Query<(&Transform, &Player, &Velocity, &JumpState)>,
+    target_query: Query<&FootTarget>,
+    mut anchor_query: Query<&mut Transform, (With<FootAnchor>, Without<Player>)>,
+    rapier_context: Res<RapierContext>,
+) {
+    for foot_target in target_query.iter() {
+        let Ok((player_transform, player, velocity, jump_state)) =
+            player_query.get(foot_target.owner)
+        else {
+            continue;
+        };
+        if jump_state.phase != JumpPhase::Airborne {
+            continue;
+        }
+        let Ok(mut anchor_transform) = anchor_query.get_mut(foot_target.anchor) else {
+            continue;
+        };
+
+        let mut rest = foot_target.pos_offset * player.walk_spread * (1.0 - jump_state.tuck);
+        rest.y = jump_state.tuck_height;
+        let mut desired = player_transform.translation + player_transform.rotation * rest;
+
+        if velocity.linvel.y < 0.0 {
+            let filter = QueryFilter::default()
+                .exclude_collider(foot_target.owner)
+                .groups(foot_query_groups());
+            let body_hit = rapier_context.cast_ray(
+                player_transform.translation,
+                Vec3::NEG_Y,
+                Real::MAX,
+                true,
+                filter,
+            );
+
+            if let Some((_, toi)) = body_hit {
+                let time_to_land = (toi + jump_state.tuck_height) / -velocity.linvel.y;
+                if time_to_land < jump_state.landing_lookahead {
+                    let mut predicted = desired + velocity.linvel * time_to_land.max(0.0);
+                    predicted.y = player_transform.translation.y;
+                    if let Some((_, foot_toi)) =
+                        rapier_context.cast_ray(predicted, Vec3::NEG_Y, Real::MAX, true, filter)
+                    {
+                        let landing_spot = predicted + Vec3::NEG_Y * foot_toi;
+                        let reach = 1.0 - time_to_land.max(0.0) / jump_state.landing_lookahead;
+                        desired = desired.lerp(landing_spot, reach);
+                    }
+                }
+            }
+        }
+
+        commands
+            .entity(foot_target.anchor)
+            .remove::<Animator<Transform>>();
+        let blend = (time.delta_seconds() * 12.0).min(1.0);
+        anchor_transform.translation = anchor_transform.translation.lerp(desired, blend);
+    }
+}
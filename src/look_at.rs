@@ -0,0 +1,168 @@
+use std::f32::consts::{PI, TAU};
+
+use bevy::{prelude::*, transform::TransformSystem};
+use regex::Regex;
+
+use crate::{
+    animation_layers::animation_layers_system,
+    components::{LookAt, LookTarget, Player, SelfRighting},
+    rig::{find_owners, RigPatterns},
+};
+
+pub struct LookAtPlugin;
+
+impl Plugin for LookAtPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(bind_spine_system)
+            .add_system(look_at_turn_system)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                look_at_spine_system
+                    .after(animation_layers_system)
+                    .before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+// the spine is looked up by name under the scene root once the model has spawned
+pub fn bind_spine_system(
+    added_player_query: Query<Entity, Added<AnimationPlayer>>,
+    parents: Query<&Parent>,
+    children: Query<&Children>,
+    names: Query<&Name>,
+    mut look_query: Query<(&mut LookAt, Option<&RigPatterns>)>,
+) {
+    for added_entity in added_player_query.iter() {
+        let owners = find_owners(added_entity, &parents, |entity| look_query.contains(entity));
+        let [owner] = owners[..] else {
+            continue;
+        };
+        let Ok((mut look_at, patterns)) = look_query.get_mut(owner) else {
+            continue;
+        };
+        let pattern = patterns.cloned().unwrap_or_default().spine;
+        let Ok(spine) = Regex::new(&pattern) else {
+            warn!("Invalid spine pattern {:?} on {:?}", pattern, owner);
+            continue;
+        };
+
+        let mut stack = vec![added_entity];
+        while let Some(entity) = stack.pop() {
+            if names
+                .get(entity)
+                .map_or(false, |name| spine.is_match(name.as_str()))
+            {
+                look_at.spine = Some(entity);
+                break;
+            }
+            if let Ok(entity_children) = children.get(entity) {
+                stack.extend(entity_children.iter());
+            }
+        }
+        if look_at.spine.is_none() {
+            warn!(
+                "No spine bone matches {:?} on {:?}, only the body turns",
+                pattern, owner
+            );
+        }
+    }
+}
+
+// eases the spine twist towards the target within its limits, the body turns for the rest
+pub fn look_at_turn_system(
+    time: Res<Time>,
+    mut query: Query<(
+        &mut LookAt,
+        &mut Player,
+        &mut Transform,
+        Option<&SelfRighting>,
+    )>,
+    targets: Query<&GlobalTransform>,
+) {
+    let delta = time.delta_seconds();
+
+    for (mut look_at, mut player, mut transform, self_righting) in query.iter_mut() {
+        let target = match look_at.target {
+            Some(LookTarget::Entity(entity)) => {
+                targets.get(entity).ok().map(|target| target.translation())
+            }
+            Some(LookTarget::Point(point)) => Some(point),
+            None => None,
+        };
+
+        // body space, the model faces -z
+        let (yaw, pitch) = target
+            .map(|target| transform.rotation.inverse() * (target - transform.translation))
+            .filter(|local| local.length_squared() > f32::EPSILON)
+            .map(|local| {
+                let yaw = (-local.x).atan2(-local.z);
+                let pitch = local.y.atan2(Vec2::new(local.x, local.z).length());
+                (yaw, pitch)
+            })
+            .unwrap_or((0.0, 0.0));
+
+        // walking fades the look out so it doesn't fight the gait
+        let speed = if player.move_speed > 0.0 {
+            (player.planar_speed() / player.move_speed).min(1.0)
+        } else {
+            0.0
+        };
+        let weight = look_at.weight * (1.0 + (look_at.moving_weight - 1.0) * speed);
+
+        let blend = (look_at.spine_speed * delta).min(1.0);
+        let wanted_yaw = yaw.clamp(-look_at.max_yaw, look_at.max_yaw) * weight;
+        let wanted_pitch = pitch.clamp(-look_at.max_pitch, look_at.max_pitch) * weight;
+        look_at.yaw += (wanted_yaw - look_at.yaw) * blend;
+        look_at.pitch += (wanted_pitch - look_at.pitch) * blend;
+
+        // physics owns the orientation while the body is unlocked
+        if self_righting.map_or(false, |self_righting| self_righting.unlock_rotation) {
+            continue;
+        }
+        let excess = (yaw - yaw.clamp(-look_at.max_yaw, look_at.max_yaw)) * weight;
+        if excess != 0.0 {
+            let max_turn = look_at.turn_speed * delta;
+            player.heading =
+                (player.heading + excess.clamp(-max_turn, max_turn) + PI).rem_euclid(TAU) - PI;
+            transform.rotation = player.body_rotation();
+        }
+    }
+}
+
+// applied on top of the animation layers, before transforms are propagated
+pub fn look_at_spine_system(
+    mut look_query: Query<(&mut LookAt, &GlobalTransform)>,
+    mut transforms: Query<&mut Transform>,
+    parents: Query<&Parent>,
+    globals: Query<&GlobalTransform>,
+) {
+    for (mut look_at, body_transform) in look_query.iter_mut() {
+        let Some(spine) = look_at.spine else {
+            continue;
+        };
+        let Some(parent_transform) = parents
+            .get(spine)
+            .ok()
+            .and_then(|parent| globals.get(parent.get()).ok())
+        else {
+            continue;
+        };
+        let Ok(mut spine_transform) = transforms.get_mut(spine) else {
+            continue;
+        };
+
+        // an animation layer wrote the spine this frame, twist on top of that pose
+        if look_at.spine_written != Some(spine_transform.rotation) {
+            look_at.spine_base = spine_transform.rotation;
+        }
+
+        let twist = Quat::from_rotation_y(look_at.yaw) * Quat::from_rotation_x(look_at.pitch);
+        // from the spine parent space to body space
+        let (_, body_rotation, _) = body_transform.to_scale_rotation_translation();
+        let (_, parent_rotation, _) = parent_transform.to_scale_rotation_translation();
+        let to_body = body_rotation.inverse() * parent_rotation;
+
+        spine_transform.rotation = (to_body.inverse() * twist * to_body) * look_at.spine_base;
+        look_at.spine_written = Some(spine_transform.rotation);
+    }
+}
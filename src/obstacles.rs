@@ -0,0 +1,324 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::components::{Ground, SurfaceMaterial};
+
+// everything is derived from the seed, the same file always builds the same scene
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ObstacleScene {
+    pub seed: u64,
+    pub obstacles: Vec<Obstacle>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Obstacle {
+    // stacked loose boxes the crab can push around
+    BoxField {
+        center: Vec3,
+        columns: usize,
+        layers: usize,
+        min_half_size: f32,
+        max_half_size: f32,
+        mass: f32,
+    },
+    Pillar {
+        position: Vec3,
+        half_size: f32,
+        height: f32,
+    },
+    Ramp {
+        position: Vec3,
+        yaw: f32,
+        length: f32,
+        width: f32,
+        height: f32,
+    },
+    // a row of flat stones with random height and sideways jitter
+    SteppingStones {
+        start: Vec3,
+        yaw: f32,
+        count: usize,
+        spacing: f32,
+        half_size: f32,
+        height: f32,
+        height_jitter: f32,
+        jitter: f32,
+    },
+    // two platforms with a hole between them
+    Gap {
+        position: Vec3,
+        yaw: f32,
+        gap: f32,
+        length: f32,
+        width: f32,
+        height: f32,
+    },
+}
+
+impl Default for ObstacleScene {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            obstacles: vec![Obstacle::BoxField {
+                center: Vec3::new(0.0, 3.0, 0.0),
+                columns: 4,
+                layers: 10,
+                min_half_size: 0.1,
+                max_half_size: 0.2,
+                mass: 300.0,
+            }],
+        }
+    }
+}
+
+impl ObstacleScene {
+    // the random ranges must not be empty or spawning panics
+    pub fn validate(&self) -> Result<(), String> {
+        for (index, obstacle) in self.obstacles.iter().enumerate() {
+            match obstacle {
+                Obstacle::BoxField {
+                    min_half_size,
+                    max_half_size,
+                    ..
+                } if min_half_size > max_half_size => {
+                    return Err(format!(
+                        "obstacle {}: min_half_size {} is over max_half_size {}",
+                        index, min_half_size, max_half_size
+                    ));
+                }
+                Obstacle::SteppingStones {
+                    height_jitter,
+                    jitter,
+                    ..
+                } if *height_jitter < 0.0 || *jitter < 0.0 => {
+                    return Err(format!(
+                        "obstacle {}: height_jitter {} and jitter {} can't be negative",
+                        index, height_jitter, jitter
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn spawn_obstacles(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    scene: &ObstacleScene,
+) -> Vec<Entity> {
+    let mut entities = Vec::new();
+    let material = materials.add(Color::rgb(0.8, 0.7, 0.6).into());
+    let stone_material = materials.add(Color::rgb(0.5, 0.5, 0.52).into());
+
+    for (index, obstacle) in scene.obstacles.iter().enumerate() {
+        // one rng per obstacle so editing one entry doesn't reshuffle the others
+        let mut rng = StdRng::seed_from_u64(scene.seed.wrapping_add(index as u64));
+
+        match obstacle {
+            Obstacle::BoxField {
+                center,
+                columns,
+                layers,
+                min_half_size,
+                max_half_size,
+                mass,
+            } => {
+                for j in 0..*layers {
+                    for i in 0..*columns {
+                        for k in 0..*columns {
+                            let rad = rng.gen_range(*min_half_size..=*max_half_size);
+                            let shift = rad * 3.0;
+                            let offset = -(*columns as f32) * shift * 0.5;
+                            let centerxz = shift * (columns / 2) as f32;
+
+                            let x = i as f32 * shift - centerxz + offset;
+                            let y = j as f32 * shift + shift / 2.0;
+                            let z = k as f32 * shift - centerxz + offset;
+
+                            let entity = commands.spawn((
+                                PbrBundle {
+                                    mesh: meshes.add(Mesh::from(shape::Cube { size: rad * 2.0 })),
+                                    material: material.clone(),
+                                    transform: Transform::from_translation(
+                                        *center + Vec3::new(x, y, z),
+                                    ),
+                                    ..default()
+                                },
+                                Name::new(format!("Box_{}_{}_{}_{}", index, i, j, k)),
+                                AdditionalMassProperties::Mass(*mass),
+                                RigidBody::Dynamic,
+                                Collider::cuboid(rad, rad, rad),
+                                Velocity::default(), // read by crabs standing on the box
+                                SurfaceMaterial::Wood,
+                            ));
+                            entities.push(entity.id());
+                        }
+                    }
+                }
+            }
+            Obstacle::Pillar {
+                position,
+                half_size,
+                height,
+            } => {
+                let half_extents = Vec3::new(*half_size, height * 0.5, *half_size);
+                let transform = Transform::from_translation(*position + Vec3::Y * height * 0.5);
+                entities.push(spawn_block(
+                    commands,
+                    meshes,
+                    stone_material.clone(),
+                    transform,
+                    half_extents,
+                    format!("Pillar_{}", index),
+                ));
+            }
+            Obstacle::Ramp {
+                position,
+                yaw,
+                length,
+                width,
+                height,
+            } => {
+                let thickness = 0.1;
+                let slope = height.atan2(*length);
+                let half_extents = Vec3::new(
+                    width * 0.5,
+                    thickness,
+                    (length * length + height * height).sqrt() * 0.5,
+                );
+                let rotation = Quat::from_rotation_y(*yaw) * Quat::from_rotation_x(slope);
+                // low end at the position, rising along -z
+                let center = *position
+                    + Quat::from_rotation_y(*yaw) * Vec3::new(0.0, height * 0.5, -length * 0.5);
+                entities.push(spawn_block(
+                    commands,
+                    meshes,
+                    material.clone(),
+                    Transform::from_translation(center).with_rotation(rotation),
+                    half_extents,
+                    format!("Ramp_{}", index),
+                ));
+            }
+            Obstacle::SteppingStones {
+                start,
+                yaw,
+                count,
+                spacing,
+                half_size,
+                height,
+                height_jitter,
+                jitter,
+            } => {
+                let rotation = Quat::from_rotation_y(*yaw);
+                for n in 0..*count {
+                    let stone_height =
+                        (height + rng.gen_range(-*height_jitter..=*height_jitter)).max(0.02);
+                    let side = rng.gen_range(-*jitter..=*jitter);
+                    let local = Vec3::new(side, stone_height * 0.5, -(n as f32) * spacing);
+                    entities.push(spawn_block(
+                        commands,
+                        meshes,
+                        stone_material.clone(),
+                        Transform::from_translation(*start + rotation * local)
+                            .with_rotation(rotation),
+                        Vec3::new(*half_size, stone_height * 0.5, *half_size),
+                        format!("Stone_{}_{}", index, n),
+                    ));
+                }
+            }
+            Obstacle::Gap {
+                position,
+                yaw,
+                gap,
+                length,
+                width,
+                height,
+            } => {
+                let rotation = Quat::from_rotation_y(*yaw);
+                let half_extents = Vec3::new(width * 0.5, height * 0.5, length * 0.5);
+                for (n, side) in [-1.0, 1.0].into_iter().enumerate() {
+                    let local = Vec3::new(0.0, height * 0.5, side * (gap + length) * 0.5);
+                    entities.push(spawn_block(
+                        commands,
+                        meshes,
+                        stone_material.clone(),
+                        Transform::from_translation(*position + rotation * local)
+                            .with_rotation(rotation),
+                        half_extents,
+                        format!("Gap_{}_{}", index, n),
+                    ));
+                }
+            }
+        }
+    }
+    entities
+}
+
+fn spawn_block(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    material: Handle<StandardMaterial>,
+    transform: Transform,
+    half_extents: Vec3,
+    name: String,
+) -> Entity {
+    let size = half_extents * 2.0;
+    commands
+        .spawn((
+            PbrBundle {
+                mesh: meshes.add(Mesh::from(shape::Box::new(size.x, size.y, size.z))),
+                material,
+                transform,
+                ..default()
+            },
+            Name::new(name),
+            RigidBody::Fixed,
+            Collider::cuboid(half_extents.x, half_extents.y, half_extents.z),
+            Ground {}, // static, feet plant on it like on the floor
+            SurfaceMaterial::Rock,
+        ))
+        .id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_random_ranges_are_rejected() {
+        let boxes = ObstacleScene {
+            seed: 0,
+            obstacles: vec![Obstacle::BoxField {
+                center: Vec3::ZERO,
+                columns: 1,
+                layers: 1,
+                min_half_size: 0.3,
+                max_half_size: 0.2,
+                mass: 1.0,
+            }],
+        };
+        assert!(boxes.validate().is_err());
+
+        let stones = ObstacleScene {
+            seed: 0,
+            obstacles: vec![Obstacle::SteppingStones {
+                start: Vec3::ZERO,
+                yaw: 0.0,
+                count: 3,
+                spacing: 1.0,
+                half_size: 0.3,
+                height: 0.2,
+                height_jitter: 0.1,
+                jitter: -0.1,
+            }],
+        };
+        assert!(stones.validate().is_err());
+        assert!(ObstacleScene::default().validate().is_ok());
+    }
+}
@@ -0,0 +1,219 @@
+use std::f32::consts::FRAC_PI_3;
+
+use bevy::prelude::*;
+use bevy_mod_inverse_kinematics::IkConstraint;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    components::{
+        FootAnchor, FootTarget, LegRagdoll, MoveAnchorEvent, Player, RagdollSegment,
+        StoredIkConstraint,
+    },
+    foot_colliders::{BODY_GROUP, FOOT_GROUP},
+    systems::anchor_move_event_system,
+};
+
+// shoulder, leg, foot and toe
+const RAGDOLL_BONES: usize = 4;
+
+pub struct RagdollPlugin;
+
+impl Plugin for RagdollPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(ragdoll_trigger_system)
+            .add_system(ragdoll_pose_system.after(ragdoll_trigger_system))
+            .add_system(ragdoll_recover_system.before(anchor_move_event_system));
+    }
+}
+
+pub fn ragdoll_trigger_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut player_query: Query<(
+        Entity,
+        &Player,
+        &Velocity,
+        &GlobalTransform,
+        &mut LegRagdoll,
+    )>,
+    anchor_query: Query<&FootAnchor>,
+    constraint_query: Query<&IkConstraint>,
+    parents: Query<&Parent>,
+    bone_transforms: Query<&GlobalTransform, Without<Player>>,
+) {
+    for (player_entity, player, velocity, player_transform, mut ragdoll) in player_query.iter_mut()
+    {
+        let knocked = (velocity.linvel - ragdoll.last_velocity).length() > ragdoll.knockback_speed;
+        ragdoll.last_velocity = velocity.linvel;
+
+        // the touchdown that ends the spawn drop is a big velocity change too
+        let was_landed = ragdoll.landed_once;
+        if player.grounded {
+            ragdoll.landed_once = true;
+            ragdoll.airborne_time = 0.0;
+            ragdoll.grounded_time += time.delta_seconds();
+        } else {
+            ragdoll.grounded_time = 0.0;
+            ragdoll.airborne_time += time.delta_seconds();
+        }
+
+        if ragdoll.active || !was_landed || !(knocked || ragdoll.airborne_time > ragdoll.fall_time)
+        {
+            continue;
+        }
+
+        info!("Legs going limp on {:?}", player_entity);
+        ragdoll.active = true;
+        ragdoll.grounded_time = 0.0;
+
+        let body_inverse = player_transform.affine().inverse();
+        let body_rotation = player_transform.to_scale_rotation_translation().1;
+
+        for anchor in anchor_query.iter().filter(|a| a.owner == player_entity) {
+            let Some(foot) = anchor.foot else {
+                continue;
+            };
+            let Ok(constraint) = constraint_query.get(foot) else {
+                continue;
+            };
+            ragdoll.stored_constraints.push(StoredIkConstraint {
+                foot,
+                chain_length: constraint.chain_length,
+                iterations: constraint.iterations,
+                target: constraint.target,
+                pole_target: constraint.pole_target,
+                pole_angle: constraint.pole_angle,
+            });
+            commands.entity(foot).remove::<IkConstraint>();
+
+            // shoulder first, toe last
+            let mut bones = vec![foot];
+            while bones.len() < RAGDOLL_BONES {
+                match parents.get(*bones.last().unwrap()) {
+                    Ok(parent) => bones.push(parent.get()),
+                    Err(_) => break,
+                }
+            }
+            bones.reverse();
+
+            let mut parent_body = player_entity;
+            let mut parent_rotation = body_rotation;
+            let mut parent_anchor = None;
+
+            for pair in bones.windows(2) {
+                let (Ok(bone_transform), Ok(next_transform)) =
+                    (bone_transforms.get(pair[0]), bone_transforms.get(pair[1]))
+                else {
+                    break;
+                };
+                let (_, rotation, start) = bone_transform.to_scale_rotation_translation();
+                let end = rotation.inverse() * (next_transform.translation() - start);
+
+                // bones bend around their local x axis
+                let anchor1 = parent_anchor.unwrap_or_else(|| body_inverse.transform_point3(start));
+                let mut joint: GenericJoint = RevoluteJointBuilder::new(Vec3::X)
+                    .local_anchor1(anchor1)
+                    .local_anchor2(Vec3::ZERO)
+                    .limits([-FRAC_PI_3, FRAC_PI_3])
+                    .motor_position(0.0, 20.0, 2.0)
+                    .build()
+                    .into();
+                joint.set_local_axis1(parent_rotation.inverse() * rotation * Vec3::X);
+
+                let segment = commands
+                    .spawn((
+                        TransformBundle::from(
+                            Transform::from_translation(start).with_rotation(rotation),
+                        ),
+                        RigidBody::Dynamic,
+                        Collider::capsule(Vec3::ZERO, end, ragdoll.segment_radius),
+                        CollisionGroups::new(FOOT_GROUP, Group::ALL - BODY_GROUP - FOOT_GROUP),
+                        Velocity::linear(velocity.linvel),
+                        ImpulseJoint::new(parent_body, joint),
+                        RagdollSegment {
+                            owner: player_entity,
+                            bone: pair[0],
+                        },
+                        Name::new("RagdollSegment"),
+                    ))
+                    .id();
+
+                parent_body = segment;
+                parent_rotation = rotation;
+                parent_anchor = Some(end);
+            }
+        }
+    }
+}
+
+// bones copy the rotation of their physics segment
+pub fn ragdoll_pose_system(
+    segment_query: Query<(&RagdollSegment, &GlobalTransform)>,
+    mut bone_query: Query<(&Parent, &mut Transform), Without<RagdollSegment>>,
+    global_transforms: Query<&GlobalTransform, Without<RagdollSegment>>,
+) {
+    for (segment, segment_transform) in segment_query.iter() {
+        if let Ok((parent, mut bone_transform)) = bone_query.get_mut(segment.bone) {
+            if let Ok(parent_transform) = global_transforms.get(parent.get()) {
+                let parent_rotation = parent_transform.to_scale_rotation_translation().1;
+                let segment_rotation = segment_transform.to_scale_rotation_translation().1;
+                bone_transform.rotation = parent_rotation.inverse() * segment_rotation;
+            }
+        }
+    }
+}
+
+// back to ik once the body has been on the ground for a while
+pub fn ragdoll_recover_system(
+    mut commands: Commands,
+    mut player_query: Query<(Entity, &mut LegRagdoll)>,
+    segment_query: Query<(Entity, &RagdollSegment)>,
+    mut anchor_query: Query<(&mut FootAnchor, &mut Transform)>,
+    target_query: Query<(Entity, &FootTarget)>,
+    foot_transforms: Query<&GlobalTransform, Without<FootAnchor>>,
+    mut move_event_writer: EventWriter<MoveAnchorEvent>,
+) {
+    for (player_entity, mut ragdoll) in player_query.iter_mut() {
+        if !ragdoll.active || ragdoll.grounded_time < ragdoll.recover_time {
+            continue;
+        }
+
+        info!("Standing up {:?}", player_entity);
+        ragdoll.active = false;
+
+        for (segment_entity, segment) in segment_query.iter() {
+            if segment.owner == player_entity {
+                commands.entity(segment_entity).despawn();
+            }
+        }
+
+        for stored in ragdoll.stored_constraints.drain(..) {
+            commands.entity(stored.foot).insert(IkConstraint {
+                chain_length: stored.chain_length,
+                iterations: stored.iterations,
+                target: stored.target,
+                pole_target: stored.pole_target,
+                pole_angle: stored.pole_angle,
+            });
+        }
+
+        // start every anchor where the limp foot is and step it back to its target
+        for (target_entity, foot_target) in target_query.iter() {
+            if foot_target.owner != player_entity {
+                continue;
+            }
+            if let Ok((mut anchor, mut anchor_transform)) = anchor_query.get_mut(foot_target.anchor)
+            {
+                if let Ok(foot_transform) = foot_transforms.get(foot_target.foot) {
+                    anchor_transform.translation = foot_transform.translation();
+                }
+                anchor.moving = true;
+                move_event_writer.send(MoveAnchorEvent {
+                    anchor: foot_target.anchor,
+                    target: target_entity,
+                    animation_duration: ragdoll.blend_duration,
+                });
+            }
+        }
+    }
+}
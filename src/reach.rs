@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::RigidBody;
+use bevy_tweening::{lens::TransformPositionLens, Animator, EaseFunction, Tween};
+
+use crate::components::{
+    FootAnchor, FootTarget, LegReach, LegSide, Player, RagdollSegment, ReachRequest,
+};
+
+pub struct ReachPlugin;
+
+impl Plugin for ReachPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ReachRequest>()
+            .add_system(reach_input_system)
+            .add_system(reach_request_system.after(reach_input_system))
+            .add_system(reach_return_system.after(reach_request_system));
+    }
+}
+
+// P pokes the closest loose body in reach with a front leg
+pub fn reach_input_system(
+    input: Res<Input<KeyCode>>,
+    player_query: Query<(Entity, &Player, &Transform)>,
+    bodies: Query<(&GlobalTransform, &RigidBody), (Without<Player>, Without<RagdollSegment>)>,
+    mut reach_writer: EventWriter<ReachRequest>,
+) {
+    if !input.just_pressed(KeyCode::P) {
+        return;
+    }
+    for (entity, player, player_transform) in player_query.iter() {
+        let body = player_transform.translation;
+        let closest = bodies
+            .iter()
+            .filter(|(_, rigid_body)| **rigid_body == RigidBody::Dynamic)
+            .map(|(transform, _)| transform.translation())
+            .filter(|point| point.distance(body) < player.leg_reach * 1.5)
+            .min_by(|a, b| a.distance(body).total_cmp(&b.distance(body)));
+        let Some(point) = closest else {
+            continue;
+        };
+
+        let local = player_transform.rotation.inverse() * (point - body);
+        let side = if local.x < 0.0 {
+            LegSide::Left
+        } else {
+            LegSide::Right
+        };
+        reach_writer.send(ReachRequest {
+            creature: entity,
+            leg: (side, 1),
+            point,
+            duration: Duration::from_secs_f32(1.0),
+        });
+    }
+}
+
+// moves the requested anchor to the point, the gait leaves it alone while LegReach is on it
+pub fn reach_request_system(
+    mut commands: Commands,
+    mut reader: EventReader<ReachRequest>,
+    mut anchor_query: Query<(Entity, &mut FootAnchor, &Transform)>,
+    player_query: Query<&Transform, With<Player>>,
+    bones: Query<&GlobalTransform>,
+) {
+    for event in reader.iter() {
+        let Ok(player_transform) = player_query.get(event.creature) else {
+            continue;
+        };
+        let anchor = anchor_query.iter_mut().find(|(_, anchor, _)| {
+            anchor.owner == event.creature && (anchor.side, anchor.index) == event.leg
+        });
+        let Some((anchor_entity, mut anchor, anchor_transform)) = anchor else {
+            warn!(
+                "{:?} has no {:?} leg to reach with",
+                event.creature, event.leg
+            );
+            continue;
+        };
+
+        // past the leg length the ik would only stretch towards it
+        let shoulder = anchor
+            .shoulder
+            .and_then(|shoulder| bones.get(shoulder).ok())
+            .map_or(player_transform.translation, |shoulder| {
+                shoulder.translation()
+            });
+        let point = shoulder + (event.point - shoulder).clamp_length_max(anchor.leg_length);
+        let reach_time = anchor.animation_duration.min(event.duration / 2);
+
+        let tween = Tween::new(
+            EaseFunction::QuadraticInOut,
+            reach_time,
+            TransformPositionLens {
+                start: anchor_transform.translation,
+                end: point,
+            },
+        );
+        commands.entity(anchor_entity).insert((
+            Animator::new(tween),
+            LegReach {
+                point,
+                timer: Timer::new(event.duration, TimerMode::Once),
+            },
+        ));
+        anchor.moving = true;
+        anchor.platform_local = None;
+    }
+}
+
+// steps the leg back to its target when the reach is over, it plants and rejoins the gait
+pub fn reach_return_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut anchor_query: Query<(Entity, &mut FootAnchor, &mut LegReach, &Transform)>,
+    target_query: Query<(&FootTarget, &GlobalTransform)>,
+) {
+    for (anchor_entity, mut anchor, mut reach, anchor_transform) in anchor_query.iter_mut() {
+        reach.timer.tick(time.delta());
+        if !reach.timer.finished() {
+            continue;
+        }
+
+        let target = target_query
+            .iter()
+            .find(|(foot_target, _)| foot_target.anchor == anchor_entity);
+        if let Some((foot_target, target_transform)) = target {
+            let tween = Tween::new(
+                EaseFunction::QuadraticInOut,
+                anchor.animation_duration,
+                TransformPositionLens {
+                    start: anchor_transform.translation,
+                    end: target_transform.translation(),
+                },
+            );
+            commands.entity(anchor_entity).insert(Animator::new(tween));
+            anchor.ground = foot_target.ground;
+            anchor.ground_normal = foot_target.ground_normal;
+            anchor.surface = foot_target.surface;
+        }
+        anchor.animation_timer.reset();
+        commands.entity(anchor_entity).remove::<LegReach>();
+    }
+}
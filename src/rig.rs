@@ -0,0 +1,362 @@
+use std::fmt;
+
+use bevy::{prelude::*, scene::SceneInstance};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::components::{CreatureModel, LegSide, Player};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RigSetupError {
+    InvalidPattern(String),
+    // nothing in the model matched the leg root pattern
+    MissingBone(String),
+    // the animated scene is under none or several creatures
+    AmbiguousOwner {
+        scene: Entity,
+        owners: usize,
+    },
+    ChainTooShort {
+        bone: String,
+        found: usize,
+        expected: usize,
+    },
+    // the model finished spawning without an AnimationPlayer to hook the rig on
+    NoAnimationPlayer,
+}
+
+impl fmt::Display for RigSetupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RigSetupError::InvalidPattern(pattern) => {
+                write!(f, "invalid bone pattern {:?}", pattern)
+            }
+            RigSetupError::MissingBone(pattern) => write!(f, "no bone matches {:?}", pattern),
+            RigSetupError::AmbiguousOwner { scene, owners } => {
+                write!(f, "scene {:?} is under {} creatures", scene, owners)
+            }
+            RigSetupError::ChainTooShort {
+                bone,
+                found,
+                expected,
+            } => write!(f, "leg {} has {} bones, expected {}", bone, found, expected),
+            RigSetupError::NoAnimationPlayer => write!(f, "model has no AnimationPlayer"),
+        }
+    }
+}
+
+impl std::error::Error for RigSetupError {}
+
+// result of the rig setup on the creature, legs that failed are left out
+#[derive(Component, Debug, Default)]
+pub struct RigStatus {
+    pub legs: usize,
+    pub errors: Vec<RigSetupError>,
+}
+
+impl RigStatus {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+// how leg chains are named in the model, the default matches the bundled crab
+// (`L.Shoulder.001` -> `L.Leg.001` -> `L.Foot.001` -> `L.Toe.001`)
+#[derive(Component, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct RigPatterns {
+    // matches the first bone of each leg, `side` and `index` named groups are optional
+    pub leg_root: String,
+    pub left: String,
+    pub right: String,
+    pub joints: usize,       // bones from the leg root down to the foot
+    pub chain_length: usize, // bones moved by the ik solver above the foot
+    pub ik_iterations: usize,
+    // the rest of the layout is measured on the rest pose, in fractions of the leg
+    pub stance: f32,      // of the horizontal reach the feet rest at
+    pub pole_reach: f32,  // of the horizontal reach the knee poles sit at
+    pub step_length: f32, // of the leg length a foot drifts before stepping
+    pub spine: String,    // bone twisted by LookAt
+}
+
+impl Default for RigPatterns {
+    fn default() -> Self {
+        Self {
+            leg_root: r"^(?P<side>[LR])\.Shoulder\.(?P<index>\d+)$".to_string(),
+            left: "L".to_string(),
+            right: "R".to_string(),
+            joints: 4,
+            chain_length: 2,
+            ik_iterations: 20,
+            stance: 0.5,
+            pole_reach: 1.1,
+            step_length: 0.15,
+            spine: "^Spine$".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LegChain {
+    pub side: LegSide,
+    pub index: usize, // 1 is the front leg, counted per side
+    pub bones: Vec<Entity>,
+    // rest pose in body space
+    pub root_position: Vec3,
+    pub bone_lengths: Vec<f32>,
+}
+
+impl LegChain {
+    pub fn foot(&self) -> Entity {
+        *self.bones.last().unwrap()
+    }
+
+    pub fn length(&self) -> f32 {
+        self.bone_lengths.iter().sum()
+    }
+
+    fn planar_root(&self) -> Vec3 {
+        Vec3::new(self.root_position.x, 0.0, self.root_position.z)
+    }
+
+    // direction the leg points away from the body, the rest pose toes are often curled in
+    fn outward(&self) -> Vec3 {
+        self.planar_root().try_normalize().unwrap_or(Vec3::X)
+    }
+
+    // how far out the fully stretched leg touches the ground
+    fn reach(&self, player: &Player) -> f32 {
+        let drop = player.ride_height + self.root_position.y;
+        (self.length().powi(2) - drop.powi(2)).max(0.0).sqrt()
+    }
+
+    // foot rest position, out from the leg root by the stance fraction of the reach
+    pub fn default_offset(&self, player: &Player, patterns: &RigPatterns) -> Vec3 {
+        self.planar_root() + self.outward() * self.reach(player) * patterns.stance + Vec3::Y * -0.01
+    }
+
+    // past the stretched foot at shoulder height, so knees bend up and out
+    pub fn default_pole(&self, player: &Player, patterns: &RigPatterns) -> Vec3 {
+        self.planar_root()
+            + self.outward() * self.reach(player) * patterns.pole_reach
+            + Vec3::Y * self.root_position.y
+    }
+
+    pub fn step_distance(&self, patterns: &RigPatterns) -> f32 {
+        self.length() * patterns.step_length
+    }
+
+    // neighbouring legs step out of phase, on the crab L2, R1 and R3 start inverted
+    pub fn starts_inverted(&self) -> bool {
+        let side = match self.side {
+            LegSide::Left => 0,
+            LegSide::Right => 1,
+        };
+        (self.index + side) % 2 == 0
+    }
+}
+
+// walks the model hierarchy under `root` and collects every leg chain matching the patterns
+pub fn discover_legs(
+    root: Entity,
+    owner: Entity,
+    patterns: &RigPatterns,
+    children: &Query<&Children>,
+    names: &Query<&Name>,
+    parents: &Query<&Parent>,
+    transforms: &Query<&Transform>,
+) -> (Vec<LegChain>, Vec<RigSetupError>) {
+    let Ok(leg_root) = Regex::new(&patterns.leg_root) else {
+        return (
+            Vec::new(),
+            vec![RigSetupError::InvalidPattern(patterns.leg_root.clone())],
+        );
+    };
+
+    let mut found = Vec::new();
+    let mut errors = Vec::new();
+    let mut stack = vec![root];
+    while let Some(entity) = stack.pop() {
+        let Ok(entity_children) = children.get(entity) else {
+            continue;
+        };
+        for child in entity_children.iter() {
+            let captures = names
+                .get(*child)
+                .ok()
+                .and_then(|name| leg_root.captures(name.as_str()));
+            let Some(captures) = captures else {
+                stack.push(*child);
+                continue;
+            };
+
+            let name = names
+                .get(*child)
+                .map(|name| name.to_string())
+                .unwrap_or_default();
+            let bones = follow_chain(*child, patterns.joints, children, names);
+            if bones.len() < patterns.joints.max(2) {
+                errors.push(RigSetupError::ChainTooShort {
+                    bone: name,
+                    found: bones.len(),
+                    expected: patterns.joints.max(2),
+                });
+                continue;
+            }
+            let Some(rest) = bones
+                .iter()
+                .map(|bone| body_space_transform(*bone, owner, parents, transforms))
+                .collect::<Option<Vec<_>>>()
+            else {
+                errors.push(RigSetupError::MissingBone(name));
+                continue;
+            };
+
+            let root_position = rest[0].translation;
+            let side = match captures.name("side").map(|side| side.as_str()) {
+                Some(side) if side == patterns.left => LegSide::Left,
+                Some(side) if side == patterns.right => LegSide::Right,
+                _ if root_position.x < 0.0 => LegSide::Left,
+                _ => LegSide::Right,
+            };
+            let index = captures
+                .name("index")
+                .and_then(|index| index.as_str().parse::<usize>().ok());
+            let bone_lengths = rest
+                .windows(2)
+                .map(|pair| pair[0].translation.distance(pair[1].translation))
+                .collect();
+
+            found.push((
+                index,
+                LegChain {
+                    side,
+                    index: 0,
+                    bones,
+                    root_position,
+                    bone_lengths,
+                },
+            ));
+        }
+    }
+
+    // number legs front to back on each side, by their name index or else by position
+    found.sort_by(|(a_index, a), (b_index, b)| {
+        a_index
+            .cmp(b_index)
+            .then(a.root_position.z.total_cmp(&b.root_position.z))
+    });
+    let mut legs = Vec::new();
+    for side in [LegSide::Left, LegSide::Right] {
+        let side_legs = found.iter().filter(|(_, leg)| leg.side == side);
+        for (i, (_, leg)) in side_legs.enumerate() {
+            legs.push(LegChain {
+                index: i + 1,
+                ..leg.clone()
+            });
+        }
+    }
+
+    if legs.is_empty() && errors.is_empty() {
+        errors.push(RigSetupError::MissingBone(patterns.leg_root.clone()));
+    }
+    (legs, errors)
+}
+
+// first named child at each level, leg bones have a single child bone
+fn follow_chain(
+    root: Entity,
+    joints: usize,
+    children: &Query<&Children>,
+    names: &Query<&Name>,
+) -> Vec<Entity> {
+    let mut bones = vec![root];
+    while bones.len() < joints {
+        let next = children
+            .get(*bones.last().unwrap())
+            .ok()
+            .and_then(|bone_children| {
+                bone_children
+                    .iter()
+                    .find(|child| names.get(**child).is_ok())
+                    .copied()
+            });
+        match next {
+            Some(next) => bones.push(next),
+            None => break,
+        }
+    }
+    bones
+}
+
+// composed local transforms up to the owner, global transforms aren't propagated yet
+// on the frame the scene spawns
+pub fn body_space_transform(
+    entity: Entity,
+    owner: Entity,
+    parents: &Query<&Parent>,
+    transforms: &Query<&Transform>,
+) -> Option<Transform> {
+    let mut result = *transforms.get(entity).ok()?;
+    let mut current = parents.get(entity).ok()?.get();
+    while current != owner {
+        result = transforms.get(current).ok()?.mul_transform(result);
+        current = parents.get(current).ok()?.get();
+    }
+    Some(result)
+}
+
+// every ancestor accepted by `is_owner`, closest first
+pub fn find_owners(
+    entity: Entity,
+    parents: &Query<&Parent>,
+    is_owner: impl Fn(Entity) -> bool,
+) -> Vec<Entity> {
+    let mut owners = Vec::new();
+    let mut current = entity;
+    while let Ok(parent) = parents.get(current) {
+        current = parent.get();
+        if is_owner(current) {
+            owners.push(current);
+        }
+    }
+    owners
+}
+
+// models that finished spawning without an AnimationPlayer never get a rig
+pub fn missing_animation_player_system(
+    mut commands: Commands,
+    scene_spawner: Res<SceneSpawner>,
+    models: Query<(Entity, &Parent, &SceneInstance), With<CreatureModel>>,
+    rigs: Query<(), With<RigStatus>>,
+    children: Query<&Children>,
+    animation_players: Query<(), With<AnimationPlayer>>,
+) {
+    for (model, parent, instance) in models.iter() {
+        let creature = parent.get();
+        if rigs.contains(creature) || !scene_spawner.instance_is_ready(**instance) {
+            continue;
+        }
+
+        let mut stack = vec![model];
+        let mut found = false;
+        while let Some(entity) = stack.pop() {
+            if animation_players.contains(entity) {
+                found = true;
+                break;
+            }
+            if let Ok(entity_children) = children.get(entity) {
+                stack.extend(entity_children.iter());
+            }
+        }
+
+        if !found {
+            let error = RigSetupError::NoAnimationPlayer;
+            error!("Rig setup failed on {:?}: {}", creature, error);
+            commands.entity(creature).insert(RigStatus {
+                legs: 0,
+                errors: vec![error],
+            });
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use bevy_tweening::Animator;
+
+use crate::components::{FootAnchor, FootTarget, Player, SelfRighting};
+
+pub struct SelfRightingPlugin;
+
+impl Plugin for SelfRightingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(righting_locked_axes_system)
+            .add_system(righting_detect_system)
+            .add_system(righting_torque_system.after(righting_detect_system))
+            .add_system(righting_flail_system.after(righting_detect_system));
+    }
+}
+
+pub fn righting_locked_axes_system(
+    mut query: Query<(&SelfRighting, &mut LockedAxes), Changed<SelfRighting>>,
+) {
+    for (self_righting, mut locked_axes) in query.iter_mut() {
+        let wanted = if self_righting.unlock_rotation {
+            LockedAxes::empty()
+        } else {
+            LockedAxes::ROTATION_LOCKED
+        };
+        if *locked_axes != wanted {
+            *locked_axes = wanted;
+        }
+    }
+}
+
+pub fn righting_detect_system(
+    time: Res<Time>,
+    mut query: Query<(Entity, &Transform, &Player, &mut SelfRighting)>,
+) {
+    for (entity, transform, player, mut self_righting) in query.iter_mut() {
+        if !self_righting.unlock_rotation {
+            self_righting.righting = false;
+            continue;
+        }
+
+        // measured against the surface up so climbing walls doesn't count as tipping over
+        let tilt = (transform.rotation * Vec3::Y).angle_between(player.up);
+        if !self_righting.righting && tilt > self_righting.tip_angle {
+            info!("Flipped over, righting {:?}", entity);
+            self_righting.righting = true;
+            self_righting.flail_time = 0.0;
+        } else if self_righting.righting && tilt < self_righting.recover_angle {
+            self_righting.righting = false;
+        }
+
+        if self_righting.righting {
+            self_righting.flail_time += time.delta_seconds();
+        }
+    }
+}
+
+pub fn righting_torque_system(
+    mut query: Query<(
+        &Transform,
+        &Player,
+        &Velocity,
+        &SelfRighting,
+        &mut ExternalForce,
+    )>,
+) {
+    for (transform, player, velocity, self_righting, mut external_force) in query.iter_mut() {
+        if !self_righting.righting {
+            if external_force.torque != Vec3::ZERO {
+                external_force.torque = Vec3::ZERO;
+            }
+            continue;
+        }
+
+        let body_up = transform.rotation * Vec3::Y;
+        let tilt = body_up.angle_between(player.up);
+        // fully upside down has no preferred side, roll over the body x axis
+        let axis = body_up
+            .cross(player.up)
+            .try_normalize()
+            .unwrap_or(transform.rotation * Vec3::X);
+
+        external_force.torque =
+            axis * self_righting.torque * tilt - velocity.angvel * self_righting.angular_damping;
+    }
+}
+
+// legs wave around the body while it is being righted, each leg out of phase
+pub fn righting_flail_system(
+    mut commands: Commands,
+    player_query: Query<(&Transform, &Player, &SelfRighting)>,
+    target_query: Query<&FootTarget>,
+    mut anchor_query: Query<&mut Transform, (With<FootAnchor>, Without<Player>)>,
+) {
+    for (i, foot_target) in target_query.iter().enumerate() {
+        let Ok((player_transform, player, self_righting)) = player_query.get(foot_target.owner)
+        else {
+            continue;
+        };
+        if !self_righting.righting {
+            continue;
+        }
+
+        if let Ok(mut anchor_transform) = anchor_query.get_mut(foot_target.anchor) {
+            let phase = self_righting.flail_time * self_righting.flail_speed + i as f32;
+            let wave = Vec3::new(0.0, phase.sin(), phase.cos()) * self_righting.flail_radius;
+            let rest = foot_target.pos_offset * player.walk_spread;
+
+            commands
+                .entity(foot_target.anchor)
+                .remove::<Animator<Transform>>();
+            anchor_transform.translation =
+                player_transform.translation + player_transform.rotation * (rest + wave);
+        }
+    }
+}
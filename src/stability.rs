@@ -0,0 +1,203 @@
+use bevy::prelude::*;
+
+use crate::{
+    components::{FootAnchor, Player, Stability},
+    systems::{anchor_move_event_trigger_system, LocomotionStage},
+};
+
+pub struct StabilityPlugin;
+
+impl Plugin for StabilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Stability>().add_system_to_stage(
+            LocomotionStage,
+            stability_margin_system.after(anchor_move_event_trigger_system),
+        );
+    }
+}
+
+fn project(point: Vec3, up: Vec3) -> Vec2 {
+    let point = Quat::from_rotation_arc(up, Vec3::Y) * point;
+    Vec2::new(point.x, point.z)
+}
+
+// convex hull of the points projected on the plane normal to up, counter clockwise
+pub fn support_polygon(points: &[Vec3], up: Vec3) -> Vec<Vec2> {
+    let mut points: Vec<Vec2> = points.iter().map(|point| project(*point, up)).collect();
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup_by(|a, b| a.distance_squared(*b) < f32::EPSILON);
+    if points.len() < 3 {
+        return points;
+    }
+
+    // monotone chain, lower half left to right then upper half back
+    let mut hull: Vec<Vec2> = Vec::new();
+    for reverse in [false, true] {
+        let start = hull.len();
+        let ordered: Vec<Vec2> = if reverse {
+            points.iter().rev().copied().collect()
+        } else {
+            points.clone()
+        };
+        for point in ordered {
+            while hull.len() >= start + 2 {
+                let (a, b) = (hull[hull.len() - 2], hull[hull.len() - 1]);
+                if (b - a).perp_dot(point - a) > 0.0 {
+                    break;
+                }
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        hull.pop(); // the last point starts the other half
+    }
+    hull
+}
+
+// distance from the projected center of mass to the closest support edge,
+// negative when it is outside and there is nothing holding the body up on that side
+pub fn stability_margin(support: &[Vec3], center_of_mass: Vec3, up: Vec3) -> f32 {
+    let hull = support_polygon(support, up);
+    let center = project(center_of_mass, up);
+
+    match hull.len() {
+        0 => f32::NEG_INFINITY,
+        1 => -center.distance(hull[0]),
+        2 => {
+            let (a, b) = (hull[0], hull[1]);
+            let t = ((center - a).dot(b - a) / (b - a).length_squared()).clamp(0.0, 1.0);
+            -center.distance(a + (b - a) * t)
+        }
+        _ => hull
+            .iter()
+            .zip(hull.iter().cycle().skip(1))
+            .map(|(a, b)| (*b - *a).perp_dot(center - *a) / a.distance(*b))
+            .fold(f32::INFINITY, f32::min),
+    }
+}
+
+// margin with the feet planted right now, lifts are checked in anchor_move_event_trigger_system
+pub fn stability_margin_system(
+    mut player_query: Query<(Entity, &Player, &Transform, &mut Stability)>,
+    anchor_query: Query<(&FootAnchor, &GlobalTransform)>,
+) {
+    for (entity, player, transform, mut stability) in player_query.iter_mut() {
+        let planted: Vec<Vec3> = anchor_query
+            .iter()
+            .filter(|(anchor, _)| anchor.owner == entity && !anchor.moving)
+            .map(|(_, anchor_transform)| anchor_transform.translation())
+            .collect();
+        stability.margin = stability_margin(&planted, transform.translation, player.up);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::components::{spawn_test_legs, FootPlantedEvent, LegSide, MoveAnchorEvent};
+
+    use super::*;
+
+    // corners of a 2m square around the origin on the ground
+    fn square() -> Vec<Vec3> {
+        vec![
+            Vec3::new(-1.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(-1.0, 0.0, 1.0),
+        ]
+    }
+
+    #[test]
+    fn hull_drops_collinear_and_duplicate_points() {
+        let mut points = square();
+        points.extend(square());
+        points.push(Vec3::new(0.0, 0.0, -1.0)); // on an edge
+        points.push(Vec3::new(1.0, 0.0, 0.5));
+        points.push(Vec3::new(0.2, 0.3, 0.1)); // inside, higher up
+
+        let hull = support_polygon(&points, Vec3::Y);
+        assert_eq!(hull.len(), 4, "hull {:?}", hull);
+        for corner in square() {
+            assert!(hull.contains(&Vec2::new(corner.x, corner.z)));
+        }
+        // counter clockwise, every corner turns left
+        for i in 0..hull.len() {
+            let (a, b, c) = (hull[i], hull[(i + 1) % 4], hull[(i + 2) % 4]);
+            assert!((b - a).perp_dot(c - b) > 0.0);
+        }
+    }
+
+    #[test]
+    fn margin_is_the_distance_to_the_closest_edge() {
+        let margin = |x: f32, z: f32| stability_margin(&square(), Vec3::new(x, 2.0, z), Vec3::Y);
+        assert!((margin(0.0, 0.0) - 1.0).abs() < 1e-5);
+        assert!((margin(0.5, 0.0) - 0.5).abs() < 1e-5);
+        assert!((margin(0.0, -0.9) - 0.1).abs() < 1e-5);
+        assert!((margin(2.0, 0.0) + 1.0).abs() < 1e-5);
+        assert!((margin(0.0, 1.5) + 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn margin_with_fewer_than_three_feet_is_never_stable() {
+        let center = Vec3::new(0.0, 1.0, 0.5);
+        assert_eq!(stability_margin(&[], center, Vec3::Y), f32::NEG_INFINITY);
+
+        let one = [Vec3::new(0.0, 0.0, 2.5)];
+        assert!((stability_margin(&one, center, Vec3::Y) + 2.0).abs() < 1e-5);
+        // a foot right under the body is still a single point
+        let under = [Vec3::new(0.0, 0.0, 0.5)];
+        assert_eq!(stability_margin(&under, center, Vec3::Y), 0.0);
+
+        let two = [Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)];
+        assert!((stability_margin(&two, center, Vec3::Y) + 0.5).abs() < 1e-5);
+        let past_the_end = Vec3::new(2.0, 1.0, 0.0);
+        assert!((stability_margin(&two, past_the_end, Vec3::Y) + 1.0).abs() < 1e-5);
+    }
+
+    // six legs all wanting to step at once, returns the sides of the anchors that lifted
+    fn lifted_sides(stability: Option<Stability>) -> Vec<LegSide> {
+        let mut app = App::new();
+        app.add_event::<MoveAnchorEvent>()
+            .add_event::<FootPlantedEvent>()
+            .add_system(anchor_move_event_trigger_system);
+
+        let mut creature = app.world.spawn((
+            TransformBundle::default(),
+            Player {
+                up: Vec3::Y,
+                scale: 1.0,
+                ..default()
+            },
+        ));
+        if let Some(stability) = stability {
+            creature.insert(stability);
+        }
+        let creature = creature.id();
+
+        // past the step distance but not lagging far enough to lift regardless
+        spawn_test_legs(&mut app.world, creature, 0.0, |rest| {
+            (rest, rest + Vec3::X * 0.6)
+        });
+        app.update();
+
+        let mut anchors = app.world.query::<&FootAnchor>();
+        anchors
+            .iter(&app.world)
+            .filter(|anchor| anchor.moving)
+            .map(|anchor| anchor.side)
+            .collect()
+    }
+
+    #[test]
+    fn one_side_never_lifts_all_its_legs_together() {
+        // nothing holds them back without the check
+        assert_eq!(lifted_sides(None).len(), 6);
+
+        let lifted = lifted_sides(Some(Stability::default()));
+        assert!(!lifted.is_empty());
+        for side in [LegSide::Left, LegSide::Right] {
+            let count = lifted.iter().filter(|lifted| **lifted == side).count();
+            assert!(count < 3, "all {:?} legs lifted", side);
+        }
+    }
+}
@@ -1,57 +1,228 @@
-use std::{ops::Mul, time::Duration};
+use std::{
+    f32::consts::{PI, TAU},
+    ops::Mul,
+    time::Duration,
+};
 
-use crate::components::{Foot, FootAnchor, FootPole, FootTarget, Ground, MoveAnchorEvent, Player};
-use bevy::{math::Vec3Swizzles, prelude::*};
+use crate::components::{
+    Foot, FootAnchor, FootPlantedEvent, FootPole, FootTarget, GaitProfile, Ground, JumpPhase,
+    JumpState, LegReach, MoveAnchorEvent, Player, SelfRighting, Stability, SurfaceMaterial,
+};
+use crate::foot_colliders::foot_query_groups;
+use crate::rig::RigStatus;
+use crate::stability::stability_margin;
+use bevy::{prelude::*, time::FixedTimestep, utils::HashMap};
 use bevy_rapier3d::prelude::{
     KinematicCharacterController, KinematicCharacterControllerOutput, QueryFilter, RapierContext,
-    Real,
+    Real, RigidBody, Velocity,
 };
 use bevy_tweening::{lens::*, *};
 
+// locomotion runs at a fixed rate so speed and gait don't depend on the frame rate
+pub const LOCOMOTION_TIMESTEP: f64 = 1.0 / 60.0;
+
+#[derive(StageLabel)]
+pub struct LocomotionStage;
+
+// holding shift runs at this times the move speed
+pub const RUN_SPEED_FACTOR: f32 = 1.7;
+
+// added before the plugins that put their systems in the locomotion stage
+pub struct LocomotionPlugin;
+
+impl Plugin for LocomotionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_stage_before(
+            CoreStage::Update,
+            LocomotionStage,
+            SystemStage::parallel().with_run_criteria(FixedTimestep::step(LOCOMOTION_TIMESTEP)),
+        )
+        .add_event::<MoveAnchorEvent>()
+        .add_event::<FootPlantedEvent>()
+        .add_system_to_stage(LocomotionStage, handle_move)
+        .add_system_to_stage(
+            LocomotionStage,
+            platform_velocity_system.before(handle_move),
+        )
+        .add_system_to_stage(LocomotionStage, gait_system.after(handle_move))
+        .add_system_to_stage(LocomotionStage, ledge_step_system.after(handle_move))
+        .add_system_to_stage(
+            LocomotionStage,
+            anchor_move_event_trigger_system.after(gait_system),
+        )
+        .register_type::<Player>();
+    }
+}
 
 pub fn anchor_move_event_trigger_system(
-    time: Res<Time>,
+    mut commands: Commands,
     foot_targets: Query<(Entity, &FootTarget, &GlobalTransform), Without<FootAnchor>>,
+    // reaching legs are out of the gait, the others step without waiting for them
     mut anchor_query: Query<
         (Entity, &mut FootAnchor, &GlobalTransform),
-        (With<FootAnchor>, Without<FootTarget>),
+        (With<FootAnchor>, Without<FootTarget>, Without<LegReach>),
     >,
-    player_query: Query<&Player>,
+    player_query: Query<(&Player, Option<&GaitProfile>)>,
+    stability_query: Query<(&Stability, &Transform)>,
+    righting_query: Query<&SelfRighting>,
+    jump_query: Query<&JumpState>,
+    ground_query: Query<(&GlobalTransform, &RigidBody)>,
     mut move_event_writer: EventWriter<MoveAnchorEvent>,
+    mut planted_event_writer: EventWriter<FootPlantedEvent>,
 ) {
+    // gait groups with a leg in the air and the feet on the ground, per owner
+    let mut lifted_groups: HashMap<Entity, Vec<usize>> = HashMap::new();
+    let mut planted: HashMap<Entity, Vec<(Entity, Vec3)>> = HashMap::new();
+    let mut legs_per_side: HashMap<Entity, usize> = HashMap::new();
+    for (_, anchor, _) in anchor_query.iter() {
+        let legs = legs_per_side.entry(anchor.owner).or_default();
+        *legs = (*legs).max(anchor.index);
+    }
+    for (anchor_entity, anchor, anchor_transform) in anchor_query.iter() {
+        if !anchor.moving {
+            planted
+                .entry(anchor.owner)
+                .or_default()
+                .push((anchor_entity, anchor_transform.translation()));
+        }
+        if let Ok((_, Some(gait))) = player_query.get(anchor.owner) {
+            if anchor.moving {
+                let group = gait.current.pattern.group(
+                    anchor.side,
+                    anchor.index,
+                    legs_per_side[&anchor.owner],
+                );
+                lifted_groups.entry(anchor.owner).or_default().push(group);
+            }
+        }
+    }
+
     for (target_entity, foot_target, target_transform) in foot_targets.iter() {
         if let Ok((_, mut anchor, anchor_transform)) = anchor_query.get_mut(foot_target.anchor) {
-            let player = player_query.get(foot_target.owner).unwrap();
-            let distance = anchor_transform
-                .translation()
-                .xz()
-                .distance(target_transform.translation().xz()); //only xz distance
+            // the owner may have been despawned this frame, its helpers go at the end of it
+            let Ok((player, gait)) = player_query.get(foot_target.owner) else {
+                continue;
+            };
+            let distance = player
+                .planar(anchor_transform.translation() - target_transform.translation())
+                .length(); //only distance along the surface
 
             anchor
                 .animation_timer
-                .tick(Duration::from_secs_f32(time.delta().as_secs_f32()));
+                .tick(Duration::from_secs_f64(LOCOMOTION_TIMESTEP));
 
-            if anchor.animation_timer.finished() {
+            if anchor.animation_timer.finished() && anchor.moving {
                 anchor.moving = false;
+
+                // remember where we stand on moving bodies so the anchor can follow them
+                anchor.platform_local = anchor
+                    .ground
+                    .and_then(|ground| ground_query.get(ground).ok())
+                    .filter(|(_, body)| **body != RigidBody::Fixed)
+                    .map(|(ground_transform, _)| {
+                        Transform::from_matrix(
+                            ground_transform.compute_matrix().inverse()
+                                * anchor_transform.compute_matrix(),
+                        )
+                    });
+                if anchor.platform_local.is_some() {
+                    // the finished tween would keep writing its end position
+                    commands
+                        .entity(foot_target.anchor)
+                        .remove::<Animator<Transform>>();
+                }
+
+                planted_event_writer.send(FootPlantedEvent {
+                    owner: foot_target.owner,
+                    anchor: foot_target.anchor,
+                    position: anchor_transform.translation(),
+                    normal: anchor.ground_normal,
+                    ground: anchor.ground,
+                    surface: anchor.surface,
+                });
             }
 
             if anchor.moving == true {
                 continue;
             }
 
-            if distance > player.walk_width {
-                // not using anchor.max_distance anymore for debugging purposes
-                let speed = 2.0
-                    + 15.0
-                        * (player.current_speed.x.powi(2) + player.current_speed.z.powi(2))
-                            .sqrt()
-                            .powi(2);
+            // legs are flailing, no stepping until the body is upright again
+            if righting_query
+                .get(foot_target.owner)
+                .map_or(false, |self_righting| self_righting.righting)
+            {
+                continue;
+            }
+
+            // airborne feet are placed by the jump system
+            if jump_query
+                .get(foot_target.owner)
+                .map_or(false, |jump_state| jump_state.phase == JumpPhase::Airborne)
+            {
+                continue;
+            }
+
+            let max_duration = anchor.animation_duration.as_secs_f32();
+            // the rig measures max_distance per leg, longer strides let the feet drift further
+            let (step_distance, step_duration) = match gait {
+                Some(gait) => {
+                    // swing takes the part of the cycle the foot is not planted
+                    let params = gait.current;
+                    let speed = player.planar_speed();
+                    let stance_time = params.stride_length / speed.max(f32::EPSILON);
+                    let swing_time = stance_time * (1.0 - params.duty_factor) / params.duty_factor;
+                    // not clamp, it panics when a leg is set up with less than 0.05s to step
+                    (
+                        anchor.max_distance * gait.stride_scale(),
+                        swing_time.max(0.05).min(max_duration),
+                    )
+                }
+                None => {
+                    // factor was tuned with per frame speeds at 60fps, 15.0 / 60^2 in m/s
+                    let speed = 2.0 + (15.0 / 3600.0) * player.planar_speed().powi(2);
+                    (anchor.max_distance, max_duration / speed)
+                }
+            };
+
+            if distance > step_distance {
+                let legs = legs_per_side[&anchor.owner];
+                let group =
+                    gait.map(|gait| gait.current.pattern.group(anchor.side, anchor.index, legs));
+                if let Some(group) = group {
+                    let lifted = lifted_groups.entry(foot_target.owner).or_default();
+                    if lifted.iter().any(|lifted_group| *lifted_group != group) {
+                        continue;
+                    }
+                }
+
+                // wait until the body stays over the feet left on the ground,
+                // unless this one is lagging too far behind
+                let support = planted.entry(foot_target.owner).or_default();
+                if let Ok((stability, body_transform)) = stability_query.get(foot_target.owner) {
+                    let remaining: Vec<Vec3> = support
+                        .iter()
+                        .filter(|(planted_anchor, _)| *planted_anchor != foot_target.anchor)
+                        .map(|(_, position)| *position)
+                        .collect();
+                    let margin =
+                        stability_margin(&remaining, body_transform.translation, player.up);
+                    if margin < stability.min_margin && distance < step_distance * stability.max_lag
+                    {
+                        continue;
+                    }
+                }
+                support.retain(|(planted_anchor, _)| *planted_anchor != foot_target.anchor);
+                if let Some(group) = group {
+                    lifted_groups
+                        .entry(foot_target.owner)
+                        .or_default()
+                        .push(group);
+                }
+
                 move_event_writer.send(MoveAnchorEvent {
                     anchor: foot_target.anchor,
                     target: target_entity,
-                    animation_duration: Duration::from_secs_f32(
-                        anchor.animation_duration.as_secs_f32() / speed,
-                    ),
+                    animation_duration: Duration::from_secs_f32(step_duration),
                 });
 
                 anchor.moving = true;
@@ -65,9 +236,12 @@ pub fn anchor_move_event_trigger_system(
 pub fn anchor_move_event_system(
     mut commands: Commands,
     mut reader: EventReader<MoveAnchorEvent>,
-    mut anchor_query: Query<(&mut FootAnchor, &Transform), Without<FootTarget>>,
+    mut anchor_query: Query<
+        (&mut FootAnchor, &Transform),
+        (Without<FootTarget>, Without<LegReach>),
+    >,
     target_query: Query<(&FootTarget, &GlobalTransform)>,
-    player_query: Query<&Player>,
+    player_query: Query<(&Player, Option<&GaitProfile>)>,
 ) {
     for event in reader.iter() {
         if let Ok((mut anchor, anchor_transform)) = anchor_query.get_mut(event.anchor) {
@@ -75,12 +249,18 @@ pub fn anchor_move_event_system(
                 let mut target_position = target_global_transform.translation().clone();
 
                 if anchor.inverted {
-                    if let Ok(player) = player_query.get(target.owner) {
-                        target_position += Vec3::new(0.0, player.walk_height, 0.0);
+                    if let Ok((player, gait)) = player_query.get(target.owner) {
+                        let step_height =
+                            gait.map_or(player.walk_height, |gait| gait.current.step_height);
+                        target_position += player.up * step_height;
                     }
                 }
 
                 anchor.inverted = !anchor.inverted;
+                anchor.ground = target.ground;
+                anchor.ground_normal = target.ground_normal;
+                anchor.surface = target.surface;
+                anchor.platform_local = None;
 
                 let tween = Tween::new(
                     EaseFunction::BounceInOut,
@@ -98,6 +278,107 @@ pub fn anchor_move_event_system(
     }
 }
 
+pub fn gait_system(mut player_query: Query<(&Player, &mut GaitProfile)>) {
+    for (player, mut gait) in player_query.iter_mut() {
+        gait.current = gait.sample(player.planar_speed());
+    }
+}
+
+// raises or lowers the body once the front feet have planted on a different level
+pub fn ledge_step_system(
+    mut player_query: Query<(
+        Entity,
+        &Player,
+        &Transform,
+        &mut KinematicCharacterController,
+    )>,
+    anchor_query: Query<(&FootAnchor, &GlobalTransform), Without<LegReach>>,
+) {
+    const MIN_STEP: f32 = 0.1;
+    let delta = LOCOMOTION_TIMESTEP as f32;
+
+    for (player_entity, player, player_transform, mut controller) in player_query.iter_mut() {
+        let Some(forward) = player.planar(player.current_speed).try_normalize() else {
+            continue;
+        };
+        let body = player_transform.translation;
+        let feet_level = body.dot(player.up) - player.ride_height;
+
+        // highest foot planted ahead of the body, the targets probe ahead of where the feet are
+        let front_level = anchor_query
+            .iter()
+            .filter(|(anchor, _)| anchor.owner == player_entity && !anchor.moving)
+            .map(|(_, anchor_transform)| anchor_transform.translation())
+            .filter(|position| (*position - body).dot(forward) > 0.0)
+            .map(|position| position.dot(player.up))
+            .reduce(f32::max);
+
+        let Some(front_level) = front_level else {
+            continue;
+        };
+        let step = front_level - feet_level;
+        if step.abs() < MIN_STEP * player.scale || step.abs() > player.max_step_height() {
+            continue;
+        }
+
+        let max_climb = player.step_climb_speed * delta;
+        let climb = step.clamp(-max_climb, max_climb);
+        controller.translation =
+            Some(controller.translation.unwrap_or(Vec3::ZERO) + player.up * climb);
+    }
+}
+
+// planted anchors ride along with the body they stand on
+pub fn anchor_platform_follow_system(
+    mut anchor_query: Query<(&FootAnchor, &mut Transform)>,
+    ground_query: Query<&GlobalTransform>,
+) {
+    for (anchor, mut anchor_transform) in anchor_query.iter_mut() {
+        if anchor.moving {
+            continue;
+        }
+        if let (Some(local), Some(ground)) = (anchor.platform_local, anchor.ground) {
+            if let Ok(ground_transform) = ground_query.get(ground) {
+                *anchor_transform = Transform::from_matrix(
+                    ground_transform.compute_matrix() * local.compute_matrix(),
+                );
+            }
+        }
+    }
+}
+
+// average velocity under the feet planted on platforms, so the body moves with them
+// even when only some of the feet stand on one
+pub fn platform_velocity_system(
+    mut player_query: Query<(Entity, &mut Player)>,
+    anchor_query: Query<(&FootAnchor, &GlobalTransform)>,
+    ground_query: Query<(&Velocity, &GlobalTransform)>,
+) {
+    for (player_entity, mut player) in player_query.iter_mut() {
+        let mut on_platform = 0;
+        let mut velocity = Vec3::ZERO;
+
+        for (anchor, anchor_transform) in anchor_query.iter() {
+            if anchor.owner != player_entity || anchor.moving || anchor.platform_local.is_none() {
+                continue;
+            }
+            if let Some(Ok((ground_velocity, ground_transform))) =
+                anchor.ground.map(|ground| ground_query.get(ground))
+            {
+                let arm = anchor_transform.translation() - ground_transform.translation();
+                velocity += ground_velocity.linvel + ground_velocity.angvel.cross(arm);
+                on_platform += 1;
+            }
+        }
+
+        player.platform_velocity = if on_platform > 0 {
+            velocity / on_platform as f32
+        } else {
+            Vec3::ZERO
+        };
+    }
+}
+
 // foot target at body side
 pub fn target_at_side_system(
     mut foot_targets: Query<(&FootTarget, &mut Transform), Without<Player>>,
@@ -105,35 +386,71 @@ pub fn target_at_side_system(
 ) {
     for (foot_target, mut target_transform) in foot_targets.iter_mut() {
         if let Ok((player, player_transform)) = player_query.get(foot_target.owner) {
-            target_transform.translation.x =
-                player_transform.translation.x + foot_target.pos_offset.x * player.walk_spread.x;
-            target_transform.translation.z =
-                player_transform.translation.z + foot_target.pos_offset.z * player.walk_spread.z;
+            // keep the height along up found by target_height_system, move along the surface
+            let height =
+                (target_transform.translation - player_transform.translation).dot(player.up);
+            let side = Vec3::new(
+                foot_target.pos_offset.x * player.walk_spread.x,
+                0.0,
+                foot_target.pos_offset.z * player.walk_spread.z,
+            );
+            target_transform.translation =
+                player_transform.translation + player.body_rotation() * side + player.up * height;
         }
     }
 }
 
 pub fn target_height_system(
-    mut foot_targets: Query<(Entity, &FootTarget, &mut Transform), Without<Player>>,
+    mut foot_targets: Query<(Entity, &mut FootTarget, &mut Transform), Without<Player>>,
     ground_query: Query<&Ground>,
+    surface_query: Query<&SurfaceMaterial>,
+    anchor_query: Query<&FootAnchor>,
     name_query: Query<&Name>,
+    player_query: Query<(&Player, &Transform)>,
     rapier_context: Res<RapierContext>,
     mut move_event_writer: EventWriter<MoveAnchorEvent>,
 ) {
-    for (foot_target_entity, foot_target, mut target_transform) in foot_targets.iter_mut() {
+    for (foot_target_entity, mut foot_target, mut target_transform) in foot_targets.iter_mut() {
+        let Ok((player, player_transform)) = player_query.get(foot_target.owner) else {
+            continue;
+        };
+        let up = player.up;
+        // lowest point along up the feet can reach
+        let reach_point = player_transform.translation - up * player.leg_reach;
+
         //Calculate height using raycast
-        let ray_pos = target_transform.translation + Vec3::new(0.0, 1.0, 0.0);
-        let ray_dir = Vec3::new(0.0, -1.0, 0.0);
+        let ray_pos = target_transform.translation + up * player.scale;
+        let ray_dir = -up;
         let max_toi = Real::MAX;
         let solid = true;
-        let filter = QueryFilter::default().exclude_collider(foot_target.owner);
-        if let Some((e, toi)) = rapier_context.cast_ray(ray_pos, ray_dir, max_toi, solid, filter) {
-            let hit_point = ray_pos + ray_dir * toi;
-            target_transform.translation.y = hit_point.y;
+        let filter = QueryFilter::default()
+            .exclude_collider(foot_target.owner)
+            .groups(foot_query_groups());
+        let hit = rapier_context
+            .cast_ray_and_get_normal(ray_pos, ray_dir, max_toi, solid, filter)
+            .filter(|(_, hit)| (hit.point - reach_point).dot(up) >= 0.0);
+        foot_target.grounded = hit.is_some();
+
+        if hit.is_none() {
+            // ground too far below, let the target hang at leg reach instead of stretching
+            let planar = target_transform.translation - up * target_transform.translation.dot(up);
+            target_transform.translation = planar + up * reach_point.dot(up);
+        }
+
+        if let Some((e, hit)) = hit {
+            let hit_point = hit.point;
+            target_transform.translation = hit_point;
+            foot_target.ground = Some(e);
+            foot_target.ground_normal = hit.normal;
+            foot_target.surface = surface_query.get(e).copied().unwrap_or_default();
             //target_transform.translation.y = hit_point.y;
-            if let Err(ground) = ground_query.get(e) {
+            // once the anchor is on this body let it plant, so it can ride along
+            let standing_on_hit = anchor_query
+                .get(foot_target.anchor)
+                .map_or(false, |anchor| anchor.ground == Some(e));
+            if let (Err(ground), false) = (ground_query.get(e), standing_on_hit) {
                 if let Ok(name) = name_query.get(e) {
-                    target_transform.translation.y += 0.2; //todo: check why hit_point.y is not enough
+                    target_transform.translation += up * 0.2 * player.scale; //todo: check why hit_point.y is not enough
                     //info!("hit_point: {:?} at {:?}", name, hit_point);
                     move_event_writer.send(
                         MoveAnchorEvent {
@@ -174,7 +491,8 @@ pub fn pole_system(
     for (foot_pole, mut target_transform) in foot_poles.iter_mut() {
         if let Ok((player, player_transform)) = player_query.get(foot_pole.owner) {
             target_transform.translation = player_transform.translation
-                + (foot_pole.pos_offset + player.pole_offset) * player.pole_spread;
+                + player.body_rotation()
+                    * ((foot_pole.pos_offset + player.pole_offset) * player.pole_spread);
         }
     }
 }
@@ -195,77 +513,430 @@ pub fn force_foot_on_anchor_system(
 }
 
 pub fn handle_move(
-    time: Res<Time>,
     input: Res<Input<KeyCode>>,
     mut query: Query<
         (
             &mut Transform,
             &mut Player,
+            &mut JumpState,
             &mut KinematicCharacterController,
             Option<&KinematicCharacterControllerOutput>,
+            Option<&RigStatus>,
+            Option<&SelfRighting>,
         ),
         With<Player>,
     >,
 ) {
-    let (mut transform, mut player, mut controller, controller_output) = query.single_mut();
-    let mut desired_movement = Vec3::ZERO;
-    let mut speed = player.move_speed;
-    let x = Vec3::new(1.0, 0.0, 0.0);
-    let z = Vec3::new(0.0, 0.0, 1.0);
-
-    for key in input.get_pressed() {
-        match *key {
-            KeyCode::D => {
-                desired_movement += x;
+    let delta = LOCOMOTION_TIMESTEP as f32;
+
+    // every creature follows the keys, an arena may have none or several
+    for (
+        mut transform,
+        mut player,
+        mut jump_state,
+        mut controller,
+        controller_output,
+        rig_status,
+        self_righting,
+    ) in query.iter_mut()
+    {
+        // the rig setup found no legs to walk on, the body is left where it is
+        if rig_status.map_or(false, |status| status.legs == 0) {
+            player.current_speed = Vec3::ZERO;
+            continue;
+        }
+        let mut desired_movement = Vec3::ZERO;
+        let mut speed = player.move_speed;
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        let z = Vec3::new(0.0, 0.0, 1.0);
+
+        for key in input.get_pressed() {
+            match *key {
+                KeyCode::D => {
+                    desired_movement += x;
+                }
+                KeyCode::A => {
+                    desired_movement -= x;
+                }
+                KeyCode::W => {
+                    desired_movement -= z;
+                }
+                KeyCode::S => {
+                    desired_movement += z;
+                }
+                KeyCode::Space => {
+                    // jump_state_system takes off once the crouch is done
+                    if player.grounded && jump_state.phase == JumpPhase::Grounded {
+                        jump_state.set_phase(JumpPhase::Crouch);
+                    }
+                }
+                KeyCode::LShift => {
+                    speed *= RUN_SPEED_FACTOR;
+                }
+                _ => {}
             }
-            KeyCode::A => {
-                desired_movement -= x;
+        }
+
+        // input is relative to the surface the crab walks on
+        desired_movement = player.surface_rotation() * desired_movement;
+        controller.up = player.up;
+
+        if player.jumping {
+            desired_movement += player.up * player.jump_power;
+            player.jump_time += delta;
+
+            if player.jump_time > player.jump_time_max {
+                player.jumping = false;
             }
-            KeyCode::W => {
-                desired_movement -= z;
+        }
+
+        if let Some(&KinematicCharacterControllerOutput {
+            grounded,
+            effective_translation,
+            ..
+        }) = controller_output
+        {
+            player.grounded = grounded;
+
+            // turn about up towards where the body went, physics owns an unlocked body
+            let local = player.surface_rotation().inverse() * player.planar(effective_translation);
+            let unlocked =
+                self_righting.map_or(false, |self_righting| self_righting.unlock_rotation);
+            if !unlocked && (local.x != 0.0 || local.z != 0.0) {
+                let angle = (-local.z).atan2(local.x);
+                let turn = ((angle - player.heading + PI).rem_euclid(TAU) - PI)
+                    * (player.rotate_speed * delta).min(1.0);
+                player.heading = (player.heading + turn + PI).rem_euclid(TAU) - PI;
+                transform.rotation = player.body_rotation();
             }
-            KeyCode::S => {
-                desired_movement += z;
+        }
+
+        // m/s, the controller consumes the summed translation of all ticks
+        // since the last physics step
+        desired_movement *= speed;
+        player.current_speed = desired_movement;
+        let translation = (desired_movement + player.platform_velocity) * delta;
+        controller.translation = Some(controller.translation.unwrap_or(Vec3::ZERO) + translation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use bevy::{scene::ScenePlugin, time::TimeUpdateStrategy};
+    use bevy_rapier3d::prelude::{Collider, NoUserData, RapierPhysicsPlugin};
+
+    use super::*;
+    use crate::{components::spawn_test_legs, jump::JumpPlugin};
+
+    #[derive(Resource, Default)]
+    struct StepCount(usize);
+
+    // ground height at a world x, the creature walks along +x
+    #[derive(Resource)]
+    struct TestGround(fn(f32) -> f32);
+
+    // how far the body rose above the feet it stands on, should stay at the ride height
+    #[derive(Resource, Default)]
+    struct MaxOvershoot(f32);
+
+    // stands in for rapier, moves the body by what the controller was given this tick
+    // and reports it standing on the ground
+    fn apply_controller_system(
+        mut query: Query<
+            (
+                &mut Transform,
+                &mut GlobalTransform,
+                &mut KinematicCharacterController,
+                &mut KinematicCharacterControllerOutput,
+            ),
+            With<Player>,
+        >,
+    ) {
+        for (mut transform, mut global, mut controller, mut output) in query.iter_mut() {
+            if let Some(translation) = controller.translation.take() {
+                transform.translation += translation;
+                *global = GlobalTransform::from(*transform);
+                output.grounded = true;
+                output.effective_translation = translation;
             }
-            KeyCode::Space => {
-                if player.grounded {
-                    player.jumping = true;
-                    player.jump_time = 0.0;
-                }
+        }
+    }
+
+    // stands in for target_at_side_system and target_height_system
+    fn follow_body_system(
+        ground: Res<TestGround>,
+        body_query: Query<&Transform, With<Player>>,
+        mut target_query: Query<(&FootTarget, &mut GlobalTransform), Without<Player>>,
+    ) {
+        for (foot_target, mut global) in target_query.iter_mut() {
+            if let Ok(body) = body_query.get(foot_target.owner) {
+                let mut position = body.translation + foot_target.pos_offset;
+                position.y = (ground.0)(position.x);
+                *global = GlobalTransform::from_translation(position);
             }
-            KeyCode::LShift => {
-                speed *= 1.7;
+        }
+    }
+
+    // stands in for the tween, the anchor lands on its target right away
+    fn snap_anchor_system(
+        mut reader: EventReader<MoveAnchorEvent>,
+        target_query: Query<&GlobalTransform, (With<FootTarget>, Without<FootAnchor>)>,
+        mut anchor_query: Query<&mut GlobalTransform, With<FootAnchor>>,
+        mut steps: ResMut<StepCount>,
+    ) {
+        for event in reader.iter() {
+            steps.0 += 1;
+            if let (Ok(target), Ok(mut anchor)) = (
+                target_query.get(event.target),
+                anchor_query.get_mut(event.anchor),
+            ) {
+                *anchor = *target;
             }
-            _ => {}
         }
     }
 
-    if player.jumping {
-        desired_movement.y = player.jump_power;
-        player.jump_time += time.delta_seconds();
+    fn overshoot_system(
+        body_query: Query<(&Player, &Transform)>,
+        anchor_query: Query<(&FootAnchor, &GlobalTransform)>,
+        mut overshoot: ResMut<MaxOvershoot>,
+    ) {
+        for (player, transform) in body_query.iter() {
+            let highest = anchor_query
+                .iter()
+                .filter(|(anchor, _)| !anchor.moving)
+                .map(|(_, anchor_transform)| anchor_transform.translation().y)
+                .fold(f32::NEG_INFINITY, f32::max);
+            let over = transform.translation.y - player.ride_height - highest;
+            overshoot.0 = overshoot.0.max(over);
+        }
+    }
+
+    // locomotion with the body moved and the feet planted by hand, holding D
+    fn locomotion_app(ground: fn(f32) -> f32) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(LocomotionPlugin)
+            .add_plugin(JumpPlugin)
+            .init_resource::<StepCount>()
+            .init_resource::<MaxOvershoot>()
+            .insert_resource(TestGround(ground))
+            .add_system_to_stage(
+                LocomotionStage,
+                apply_controller_system
+                    .after(handle_move)
+                    .after(ledge_step_system),
+            )
+            .add_system_to_stage(
+                LocomotionStage,
+                snap_anchor_system.after(anchor_move_event_trigger_system),
+            )
+            .add_system_to_stage(
+                LocomotionStage,
+                overshoot_system.after(apply_controller_system),
+            );
+
+        let mut input = Input::<KeyCode>::default();
+        input.press(KeyCode::D);
+        app.insert_resource(input);
+        app
+    }
+
+    // a six legged creature over the given ground, its targets follow the body
+    fn creature_app(ground: fn(f32) -> f32) -> (App, Entity) {
+        let mut app = locomotion_app(ground);
+        app.insert_resource(RapierContext::default())
+            .add_system_to_stage(
+                LocomotionStage,
+                follow_body_system
+                    .after(apply_controller_system)
+                    .before(anchor_move_event_trigger_system),
+            );
+        let player = spawn_creature(&mut app, ground);
+        (app, player)
+    }
+
+    fn spawn_creature(app: &mut App, ground: fn(f32) -> f32) -> Entity {
+        let ride_height = 0.5;
+        let player = app
+            .world
+            .spawn((
+                Transform::from_xyz(0.0, ground(0.0) + ride_height, 0.0),
+                GlobalTransform::from_xyz(0.0, ground(0.0) + ride_height, 0.0),
+                Player {
+                    move_speed: 1.2,
+                    walk_spread: Vec3::ONE,
+                    leg_reach: 1.0,
+                    ride_height,
+                    step_climb_speed: 1.0,
+                    up: Vec3::Y,
+                    scale: 1.0,
+                    ..default()
+                },
+                JumpState::default(),
+                GaitProfile::default(),
+                KinematicCharacterController::default(),
+                KinematicCharacterControllerOutput {
+                    grounded: true,
+                    ..default()
+                },
+            ))
+            .id();
+        spawn_test_legs(&mut app.world, player, ride_height, |rest| {
+            let position = Vec3::new(rest.x, ground(rest.x), rest.z);
+            (position, position)
+        });
+        player
+    }
 
-        if player.jump_time > player.jump_time_max {
-            player.jumping = false;
+    // runs the app for the duration with frames of 1 / fps, the last one cut short
+    fn run(app: &mut App, fps: f64, duration: f64) {
+        let start = Instant::now();
+        app.insert_resource(TimeUpdateStrategy::ManualInstant(start));
+        app.update();
+        let frames = (duration * fps).ceil() as u32;
+        for frame in 1..=frames {
+            let elapsed = (frame as f64 / fps).min(duration);
+            app.insert_resource(TimeUpdateStrategy::ManualInstant(
+                start + Duration::from_secs_f64(elapsed),
+            ));
+            app.update();
         }
     }
 
-    if let Some(&KinematicCharacterControllerOutput {
-        grounded,
-        effective_translation,
-        ..
-    }) = controller_output
-    {
-        player.grounded = grounded;
+    // drops in and walks for 3s at the given frame rate,
+    // returns where the body ended and the steps taken, the landing included
+    fn walk(fps: f64) -> (Vec3, usize) {
+        let (mut app, player) = creature_app(|_| 0.0);
+        // lands on the first tick, every foot reaches for the ground once
+        app.world
+            .get_mut::<JumpState>(player)
+            .unwrap()
+            .set_phase(JumpPhase::Airborne);
+        // half a tick over so float error can't add or drop one at any rate
+        run(&mut app, fps, 3.0 + LOCOMOTION_TIMESTEP / 2.0);
+        let position = app.world.get::<Transform>(player).unwrap().translation;
+        (position, app.world.resource::<StepCount>().0)
+    }
+
+    #[test]
+    fn locomotion_does_not_depend_on_the_frame_rate() {
+        let (position, steps) = walk(60.0);
+        assert!((position.x - 3.6).abs() < 1e-3, "walked to {}", position);
+        assert!(steps > 6, "{} steps with the landing", steps);
 
-        if effective_translation.x != 0.0 || effective_translation.z != 0.0 {
-            let angle = (-effective_translation.z).atan2(effective_translation.x);
-            transform.rotation = transform
-                .rotation
-                .lerp(Quat::from_rotation_y(angle), player.rotate_speed);
+        for fps in [30.0, 144.0] {
+            let (other_position, other_steps) = walk(fps);
+            assert!(
+                other_position.abs_diff_eq(position, 1e-5),
+                "{} fps walked to {}, 60 fps to {}",
+                fps,
+                other_position,
+                position
+            );
+            assert_eq!(other_steps, steps, "steps at {} fps", fps);
         }
     }
 
-    desired_movement *= speed;
-    controller.translation = Some(desired_movement);
-    player.current_speed = desired_movement;
+    #[test]
+    fn creature_without_legs_stays_put_next_to_a_walking_one() {
+        let (mut app, player) = creature_app(|_| 0.0);
+        let legless = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                Player {
+                    move_speed: 1.2,
+                    up: Vec3::Y,
+                    scale: 1.0,
+                    ..default()
+                },
+                JumpState::default(),
+                KinematicCharacterController::default(),
+                RigStatus::default(),
+            ))
+            .id();
+        run(&mut app, 60.0, 1.0);
+
+        let walked = app.world.get::<Transform>(player).unwrap().translation;
+        let stayed = app.world.get::<Transform>(legless).unwrap().translation;
+        assert!(walked.x > 1.0, "walked to {}", walked);
+        assert_eq!(stayed, Vec3::ZERO);
+    }
+
+    // 0.2 high steps every meter from x = 0.5
+    fn stairs(x: f32) -> f32 {
+        (x + 0.5).floor().max(0.0) * 0.2
+    }
+
+    // the body stands on the feet, between the step under it and the next one,
+    // and never rose above the feet planted on the ground
+    fn assert_climbed_stairs(app: &App, player: Entity) {
+        let body = app.world.get::<Transform>(player).unwrap().translation;
+        let feet_level = body.y - 0.5;
+        assert!(
+            feet_level > stairs(body.x) - 0.1 && feet_level < stairs(body.x + 1.0) + 1e-3,
+            "body at {} over stairs at {}",
+            body,
+            stairs(body.x)
+        );
+        assert!(feet_level > 0.5, "body stayed down at {}", body);
+        // the targets reach the next step first, the body waits for the feet to plant there
+        let overshoot = app.world.resource::<MaxOvershoot>().0;
+        assert!(overshoot < 1e-3, "body rose {} above its feet", overshoot);
+    }
+
+    #[test]
+    fn body_climbs_stairs_with_the_planted_feet() {
+        let (mut app, player) = creature_app(stairs);
+        run(&mut app, 60.0, 3.0);
+        assert_climbed_stairs(&app, player);
+    }
+
+    #[test]
+    fn foot_probes_find_the_steps_of_a_collider_staircase() {
+        let mut app = locomotion_app(stairs);
+        app.add_plugin(TransformPlugin)
+            .add_plugin(HierarchyPlugin)
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<Mesh>()
+            .add_plugin(ScenePlugin)
+            .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+            .add_system(target_at_side_system)
+            .add_system(target_height_system.after(target_at_side_system));
+
+        // flat ground, then slabs with their tops on the steps of `stairs`
+        let ground = Vec3::new(0.0, -0.5, 0.0);
+        app.world.spawn((
+            Transform::from_translation(ground),
+            GlobalTransform::from_translation(ground),
+            Collider::cuboid(20.0, 0.5, 20.0),
+        ));
+        for step in 1..=8 {
+            let start = step as f32 - 0.5;
+            let center = Vec3::new((start + 10.0) / 2.0, step as f32 * 0.2 - 0.1, 0.0);
+            app.world.spawn((
+                Transform::from_translation(center),
+                GlobalTransform::from_translation(center),
+                Collider::cuboid((10.0 - start) / 2.0, 0.1, 2.0),
+            ));
+        }
+        let player = spawn_creature(&mut app, stairs);
+        run(&mut app, 60.0, 3.0);
+
+        // every probe found the step under it
+        let mut targets = app.world.query::<(&FootTarget, &GlobalTransform)>();
+        for (foot_target, target_transform) in targets.iter(&app.world) {
+            let position = target_transform.translation();
+            assert!(foot_target.grounded, "no ground under {}", position);
+            assert!(
+                (position.y - stairs(position.x)).abs() < 1e-3,
+                "target at {} over stairs at {}",
+                position,
+                stairs(position.x)
+            );
+        }
+        assert_climbed_stairs(&app, player);
+    }
 }
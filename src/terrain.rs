@@ -0,0 +1,247 @@
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::components::{Ground, SurfaceMaterial};
+
+// heightfield test terrain, the same seed always gives the same ground
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TerrainSettings {
+    pub seed: u64,
+    pub translation: Vec3,
+    pub size: f32,
+    pub resolution: usize, // vertices per side
+    pub dune_height: f32,
+    pub dune_wavelength: f32,
+    pub rock_density: f32, // 0 no rocks, 1 rocks everywhere
+    pub rock_height: f32,
+    pub rock_wavelength: f32,
+    pub slope_grade: f32, // rise over run along x
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            translation: Vec3::new(0.0, 0.0, -60.0),
+            size: 40.0,
+            resolution: 129,
+            dune_height: 1.5,
+            dune_wavelength: 12.0,
+            rock_density: 0.15,
+            rock_height: 0.4,
+            rock_wavelength: 1.5,
+            slope_grade: 0.1,
+        }
+    }
+}
+
+impl TerrainSettings {
+    // the heightfield needs at least one cell per side
+    pub fn validate(&self) -> Result<(), String> {
+        if self.resolution < 2 {
+            return Err(format!(
+                "terrain resolution is {}, it needs at least 2 vertices per side",
+                self.resolution
+            ));
+        }
+        Ok(())
+    }
+}
+
+// heights in rapier heightfield order: column major, rows along z and columns along x
+pub fn generate_heights(settings: &TerrainSettings) -> Vec<f32> {
+    let n = settings.resolution;
+    let cell = settings.size / (n - 1) as f32;
+    let mut heights = Vec::with_capacity(n * n);
+
+    for j in 0..n {
+        for i in 0..n {
+            let x = j as f32 * cell;
+            let z = i as f32 * cell;
+
+            let dunes = fbm(
+                settings.seed,
+                x / settings.dune_wavelength,
+                z / settings.dune_wavelength,
+                4,
+            );
+            let mut height = dunes * settings.dune_height;
+
+            let rocks = value_noise(
+                settings.seed.wrapping_add(1),
+                x / settings.rock_wavelength,
+                z / settings.rock_wavelength,
+            );
+            let threshold = 1.0 - settings.rock_density;
+            if settings.rock_density > 0.0 && rocks > threshold {
+                height += settings.rock_height * (rocks - threshold) / settings.rock_density;
+            }
+
+            height += x * settings.slope_grade;
+            heights.push(height);
+        }
+    }
+
+    // sit on top of the floor plane
+    let min = heights.iter().copied().fold(f32::MAX, f32::min);
+    heights.iter_mut().for_each(|height| *height -= min);
+    heights
+}
+
+pub fn spawn_terrain(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    settings: &TerrainSettings,
+) -> Entity {
+    let n = settings.resolution;
+    let heights = generate_heights(settings);
+
+    commands
+        .spawn((
+            PbrBundle {
+                mesh: meshes.add(terrain_mesh(&heights, n, settings.size)),
+                material: materials.add(StandardMaterial {
+                    base_color: Color::rgb(0.76, 0.7, 0.5),
+                    perceptual_roughness: 1.0,
+                    ..default()
+                }),
+                transform: Transform::from_translation(settings.translation),
+                ..default()
+            },
+            Collider::heightfield(heights, n, n, Vec3::new(settings.size, 1.0, settings.size)),
+            Ground {},
+            SurfaceMaterial::Sand,
+            Name::new(format!("Terrain_{}", settings.seed)),
+        ))
+        .id()
+}
+
+fn terrain_mesh(heights: &[f32], n: usize, size: f32) -> Mesh {
+    let cell = size / (n - 1) as f32;
+    let height_at = |i: usize, j: usize| heights[i + j * n];
+
+    let mut positions = Vec::with_capacity(n * n);
+    let mut normals = Vec::with_capacity(n * n);
+    let mut uvs = Vec::with_capacity(n * n);
+
+    for j in 0..n {
+        for i in 0..n {
+            let x = -0.5 * size + j as f32 * cell;
+            let z = -0.5 * size + i as f32 * cell;
+            positions.push([x, height_at(i, j), z]);
+
+            let dx = height_at(i, (j + 1).min(n - 1)) - height_at(i, j.saturating_sub(1));
+            let dz = height_at((i + 1).min(n - 1), j) - height_at(i.saturating_sub(1), j);
+            normals.push(Vec3::new(-dx, 2.0 * cell, -dz).normalize().to_array());
+
+            uvs.push([j as f32 / (n - 1) as f32, i as f32 / (n - 1) as f32]);
+        }
+    }
+
+    let index = |i: usize, j: usize| (i + j * n) as u32;
+    let mut indices = Vec::with_capacity((n - 1) * (n - 1) * 6);
+    for j in 0..n - 1 {
+        for i in 0..n - 1 {
+            indices.extend_from_slice(&[index(i, j), index(i + 1, j), index(i, j + 1)]);
+            indices.extend_from_slice(&[index(i, j + 1), index(i + 1, j), index(i + 1, j + 1)]);
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+// smooth value noise in [0, 1) from a hashed integer lattice
+fn value_noise(seed: u64, x: f32, z: f32) -> f32 {
+    let (x0, z0) = (x.floor(), z.floor());
+    let (tx, tz) = (smooth(x - x0), smooth(z - z0));
+    let (x0, z0) = (x0 as i64, z0 as i64);
+
+    let a = lattice(seed, x0, z0);
+    let b = lattice(seed, x0 + 1, z0);
+    let c = lattice(seed, x0, z0 + 1);
+    let d = lattice(seed, x0 + 1, z0 + 1);
+
+    let top = a + (b - a) * tx;
+    let bottom = c + (d - c) * tx;
+    top + (bottom - top) * tz
+}
+
+fn fbm(seed: u64, x: f32, z: f32, octaves: u32) -> f32 {
+    let mut value = 0.0;
+    let mut amplitude = 0.5;
+    let mut frequency = 1.0;
+    let mut total = 0.0;
+
+    for octave in 0..octaves {
+        value += amplitude
+            * value_noise(
+                seed.wrapping_add(octave as u64 * 101),
+                x * frequency,
+                z * frequency,
+            );
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    value / total
+}
+
+fn smooth(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+// splitmix64 of the seed and lattice coordinates
+fn lattice(seed: u64, x: i64, z: i64) -> f32 {
+    let mut h = seed
+        ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (z as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^= h >> 31;
+    (h >> 40) as f32 / (1u64 << 24) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heights_only_depend_on_the_seed() {
+        let settings = TerrainSettings {
+            resolution: 17,
+            ..default()
+        };
+        let heights = generate_heights(&settings);
+        assert_eq!(heights.len(), 17 * 17);
+        assert_eq!(heights, generate_heights(&settings));
+
+        let other = generate_heights(&TerrainSettings {
+            seed: 1,
+            ..settings
+        });
+        assert_ne!(heights, other);
+    }
+
+    #[test]
+    fn resolution_below_two_is_rejected() {
+        for resolution in [0, 1] {
+            let settings = TerrainSettings {
+                resolution,
+                ..default()
+            };
+            assert!(settings.validate().is_err());
+        }
+        assert!(TerrainSettings::default().validate().is_ok());
+    }
+}